@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+/// Volume envelope shared by both square channels and the noise channel (NRx2)
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+pub(super) struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8,
+    timer: u8,
+    pub(super) volume: u8,
+}
+
+impl Envelope {
+    pub(super) fn write(&mut self, byte: u8) {
+        self.initial_volume = byte >> 4;
+        self.increase = byte & 0b1000 != 0;
+        self.period = byte & 0b111;
+    }
+
+    /// The DAC of a channel with an envelope is powered as long as the upper 5 bits of NRx2 are set
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    pub(super) fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+
+    /// Clocked by step 7 of the frame sequencer
+    pub(super) fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer != 0 {
+            return;
+        }
+
+        self.timer = self.period;
+
+        if self.increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !self.increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triggered(byte: u8) -> Envelope {
+        let mut envelope = Envelope::default();
+        envelope.write(byte);
+        envelope.trigger();
+        envelope
+    }
+
+    #[test]
+    fn volume_changes_once_per_period() {
+        let mut envelope = triggered(0xF2);
+
+        envelope.clock();
+        assert_eq!(envelope.volume, 15);
+
+        envelope.clock();
+        assert_eq!(envelope.volume, 14);
+    }
+
+    #[test]
+    fn volume_stays_in_range() {
+        let mut decreasing = triggered(0x11);
+        let mut increasing = triggered(0xE9);
+
+        for _ in 0..4 {
+            decreasing.clock();
+            increasing.clock();
+        }
+
+        assert_eq!(decreasing.volume, 0);
+        assert_eq!(increasing.volume, 15);
+    }
+
+    #[test]
+    fn period_zero_keeps_volume() {
+        let mut envelope = triggered(0x80);
+        envelope.clock();
+
+        assert_eq!(envelope.volume, 8);
+    }
+
+    #[test]
+    fn dac_needs_volume_or_increase() {
+        assert!(!triggered(0x07).dac_enabled());
+        assert!(triggered(0x08).dac_enabled());
+        assert!(triggered(0x10).dac_enabled());
+    }
+}
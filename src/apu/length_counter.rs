@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+/// Length timer that disables its channel once it runs out. Counts up to 64 for the square and
+/// noise channels and up to 256 for the wave channel.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) struct LengthCounter {
+    max_length: u16,
+    counter: u16,
+    pub(super) enabled: bool,
+}
+
+impl LengthCounter {
+    pub(super) fn new(max_length: u16) -> Self {
+        Self {
+            max_length,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// Loads the initial length timer value written to NRx1
+    pub(super) fn load(&mut self, value: u8) {
+        self.counter = self.max_length - u16::from(value);
+    }
+
+    pub(super) fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max_length;
+        }
+    }
+
+    /// Clocked by every even step of the frame sequencer. Returns true if the channel has to be
+    /// disabled.
+    pub(super) fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+
+        self.counter == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disables_channel_when_expired() {
+        let mut length = LengthCounter::new(64);
+        length.load(62);
+        length.enabled = true;
+
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn does_not_count_while_disabled() {
+        let mut length = LengthCounter::new(64);
+        length.load(63);
+
+        assert!(!length.clock());
+
+        length.enabled = true;
+        assert!(length.clock());
+    }
+
+    #[test]
+    fn trigger_reloads_expired_counter() {
+        let mut length = LengthCounter::new(256);
+        length.load(255);
+        length.enabled = true;
+        assert!(length.clock());
+
+        length.trigger();
+
+        assert_eq!(length.counter, 256);
+    }
+}
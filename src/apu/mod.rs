@@ -0,0 +1,461 @@
+mod envelope;
+mod length_counter;
+mod noise;
+mod square;
+mod wave;
+
+use crate::emulator::CLOCK_SPEED;
+use crate::memory::bus::{get_bit_status, Bus};
+use noise::NoiseChannel;
+use serde::{Deserialize, Serialize};
+use square::SquareChannel;
+use std::collections::VecDeque;
+use wave::WaveChannel;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+/// Samples are stored interleaved, left channel first
+pub const AUDIO_CHANNELS: usize = 2;
+/// Upper bound of buffered samples in seconds, older samples are dropped if nobody collects them
+const MAX_BUFFERED_SECONDS: usize = 1;
+
+/// NR10 register address
+pub const NR10: u16 = 0xFF10;
+/// NR11 register address
+pub const NR11: u16 = 0xFF11;
+/// NR12 register address
+pub const NR12: u16 = 0xFF12;
+/// NR13 register address
+pub const NR13: u16 = 0xFF13;
+/// NR14 register address
+pub const NR14: u16 = 0xFF14;
+/// NR21 register address
+pub const NR21: u16 = 0xFF16;
+/// NR22 register address
+pub const NR22: u16 = 0xFF17;
+/// NR23 register address
+pub const NR23: u16 = 0xFF18;
+/// NR24 register address
+pub const NR24: u16 = 0xFF19;
+/// NR30 register address
+pub const NR30: u16 = 0xFF1A;
+/// NR31 register address
+pub const NR31: u16 = 0xFF1B;
+/// NR32 register address
+pub const NR32: u16 = 0xFF1C;
+/// NR33 register address
+pub const NR33: u16 = 0xFF1D;
+/// NR34 register address
+pub const NR34: u16 = 0xFF1E;
+/// NR41 register address
+pub const NR41: u16 = 0xFF20;
+/// NR42 register address
+pub const NR42: u16 = 0xFF21;
+/// NR43 register address
+pub const NR43: u16 = 0xFF22;
+/// NR44 register address
+pub const NR44: u16 = 0xFF23;
+/// NR50 register address
+pub const NR50: u16 = 0xFF24;
+/// NR51 register address
+pub const NR51: u16 = 0xFF25;
+/// NR52 register address
+pub const NR52: u16 = 0xFF26;
+const WAVE_RAM_START: u16 = 0xFF30;
+const WAVE_RAM_END: u16 = 0xFF3F;
+
+const REGISTER_COUNT: usize = (NR52 - NR10) as usize;
+/// Bits that always read back as 1, indexed from NR10. Includes write-only bits and the unused
+/// addresses 0xFF15 and 0xFF1F.
+const REGISTER_READ_MASKS: [u8; REGISTER_COUNT] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, // NR50-NR51
+];
+
+/// DIV bit whose falling edge clocks the frame sequencer at 512 Hz
+const DIV_BIT_FRAME_SEQUENCER: u8 = 4;
+const NR52_BIT_AUDIO_ENABLE: u8 = 7;
+
+/// Audio Processing Unit, see https://gbdev.io/pandocs/Audio.html
+#[derive(Clone, Serialize, Deserialize)]
+pub struct APU {
+    enabled: bool,
+    /// Last values written to NR10-NR51, used for reading back the registers
+    registers: [u8; REGISTER_COUNT],
+    channel1: SquareChannel,
+    channel2: SquareChannel,
+    channel3: WaveChannel,
+    channel4: NoiseChannel,
+    frame_sequencer_step: u8,
+    last_div: u8,
+    /// Sample generation
+    sample_rate: u32,
+    sample_counter: u32,
+    #[serde(skip)]
+    samples: VecDeque<f32>,
+}
+
+impl APU {
+    pub fn init(sample_rate: u32) -> Self {
+        Self {
+            enabled: false,
+            registers: [0; REGISTER_COUNT],
+            channel1: SquareChannel::new(true),
+            channel2: SquareChannel::new(false),
+            channel3: WaveChannel::new(),
+            channel4: NoiseChannel::new(),
+            frame_sequencer_step: 0,
+            last_div: 0,
+            sample_rate,
+            sample_counter: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_counter = 0;
+        self.samples.clear();
+    }
+
+    /// Returns all samples generated since the last call, interleaved as left/right pairs
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        self.samples.drain(..).collect()
+    }
+
+    pub(crate) fn read(&self, address: u16) -> u8 {
+        match address {
+            NR52 => {
+                let status = u8::from(self.channel1.enabled)
+                    | u8::from(self.channel2.enabled) << 1
+                    | u8::from(self.channel3.enabled) << 2
+                    | u8::from(self.channel4.enabled) << 3;
+
+                u8::from(self.enabled) << NR52_BIT_AUDIO_ENABLE | 0x70 | status
+            }
+            NR10..NR52 => {
+                let index = usize::from(address - NR10);
+                self.registers[index] | REGISTER_READ_MASKS[index]
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => self
+                .channel3
+                .read_wave_ram(usize::from(address - WAVE_RAM_START)),
+            _ => 0xFF,
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u16, byte: u8) {
+        match address {
+            NR52 => self.set_power(get_bit_status(byte, NR52_BIT_AUDIO_ENABLE)),
+            WAVE_RAM_START..=WAVE_RAM_END => self
+                .channel3
+                .write_wave_ram(usize::from(address - WAVE_RAM_START), byte),
+            // on DMG the length timers stay writable while the APU is off, all other registers are
+            // read-only
+            NR11 if !self.enabled => self.channel1.write_length(byte),
+            NR21 if !self.enabled => self.channel2.write_length(byte),
+            NR31 if !self.enabled => self.channel3.write_length(byte),
+            NR41 if !self.enabled => self.channel4.write_length(byte),
+            _ if !self.enabled => {}
+            NR10..NR52 => {
+                self.registers[usize::from(address - NR10)] = byte;
+                self.write_channel_register(address, byte);
+            }
+            _ => {}
+        }
+    }
+
+    fn write_channel_register(&mut self, address: u16, byte: u8) {
+        match address {
+            NR10 => self.channel1.write_sweep(byte),
+            NR11 => self.channel1.write_length_duty(byte),
+            NR12 => self.channel1.write_envelope(byte),
+            NR13 => self.channel1.write_frequency_low(byte),
+            NR14 => self.channel1.write_control(byte),
+            NR21 => self.channel2.write_length_duty(byte),
+            NR22 => self.channel2.write_envelope(byte),
+            NR23 => self.channel2.write_frequency_low(byte),
+            NR24 => self.channel2.write_control(byte),
+            NR30 => self.channel3.write_dac(byte),
+            NR31 => self.channel3.write_length(byte),
+            NR32 => self.channel3.write_output_level(byte),
+            NR33 => self.channel3.write_frequency_low(byte),
+            NR34 => self.channel3.write_control(byte),
+            NR41 => self.channel4.write_length(byte),
+            NR42 => self.channel4.write_envelope(byte),
+            NR43 => self.channel4.write_frequency(byte),
+            NR44 => self.channel4.write_control(byte),
+            _ => {}
+        }
+    }
+
+    /// Turning the APU off clears all registers except wave RAM, turning it on resets the frame
+    /// sequencer
+    fn set_power(&mut self, enabled: bool) {
+        if self.enabled == enabled {
+            return;
+        }
+
+        if !enabled {
+            let wave_channel = self.channel3.power_off();
+
+            *self = Self {
+                channel3: wave_channel,
+                sample_rate: self.sample_rate,
+                sample_counter: self.sample_counter,
+                samples: std::mem::take(&mut self.samples),
+                last_div: self.last_div,
+                ..Self::init(self.sample_rate)
+            };
+        }
+
+        self.frame_sequencer_step = 0;
+        self.enabled = enabled;
+    }
+
+    /// Advances all channels by the given number of t-cycles. The frame sequencer is clocked by
+    /// the falling edge of bit 4 of the DIV register.
+    pub(crate) fn step(&mut self, t_cycles: u8, div: u8) {
+        if self.enabled {
+            self.channel1.tick(t_cycles);
+            self.channel2.tick(t_cycles);
+            self.channel3.tick(t_cycles);
+            self.channel4.tick(t_cycles);
+
+            if get_bit_status(self.last_div, DIV_BIT_FRAME_SEQUENCER)
+                && !get_bit_status(div, DIV_BIT_FRAME_SEQUENCER)
+            {
+                self.clock_frame_sequencer();
+            }
+        }
+
+        self.last_div = div;
+
+        self.generate_samples(t_cycles);
+    }
+
+    /// Steps 0, 2, 4 and 6 clock the length timers, 2 and 6 the sweep and 7 the envelopes
+    fn clock_frame_sequencer(&mut self) {
+        if self.frame_sequencer_step.is_multiple_of(2) {
+            self.channel1.clock_length();
+            self.channel2.clock_length();
+            self.channel3.clock_length();
+            self.channel4.clock_length();
+        }
+
+        if self.frame_sequencer_step == 2 || self.frame_sequencer_step == 6 {
+            self.channel1.clock_sweep();
+        }
+
+        if self.frame_sequencer_step == 7 {
+            self.channel1.clock_envelope();
+            self.channel2.clock_envelope();
+            self.channel4.clock_envelope();
+        }
+
+        self.frame_sequencer_step = (self.frame_sequencer_step + 1) % 8;
+    }
+
+    fn generate_samples(&mut self, t_cycles: u8) {
+        self.sample_counter += u32::from(t_cycles) * self.sample_rate;
+
+        while self.sample_counter >= CLOCK_SPEED {
+            self.sample_counter -= CLOCK_SPEED;
+
+            let (left, right) = self.mix();
+            self.push_sample(left, right);
+        }
+    }
+
+    fn push_sample(&mut self, left: f32, right: f32) {
+        let max_samples = self.sample_rate as usize * AUDIO_CHANNELS * MAX_BUFFERED_SECONDS;
+
+        while self.samples.len() + AUDIO_CHANNELS > max_samples && !self.samples.is_empty() {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(left);
+        self.samples.push_back(right);
+    }
+
+    /// Mixes the channel outputs according to NR51 panning and NR50 master volume. Returns a
+    /// left/right sample pair in the range -1.0..=1.0.
+    fn mix(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (0.0, 0.0);
+        }
+
+        let channels = [
+            (self.channel1.output(), self.channel1.dac_enabled()),
+            (self.channel2.output(), self.channel2.dac_enabled()),
+            (self.channel3.output(), self.channel3.dac_enabled()),
+            (self.channel4.output(), self.channel4.dac_enabled()),
+        ];
+
+        let panning = self.registers[usize::from(NR51 - NR10)];
+        let master_volume = self.registers[usize::from(NR50 - NR10)];
+
+        let (mut left, mut right) = (0.0, 0.0);
+
+        for (i, (output, dac_enabled)) in channels.into_iter().enumerate() {
+            // a disabled DAC outputs silence, an enabled one maps 0..=15 to 1.0..=-1.0
+            let analog = if dac_enabled {
+                1.0 - f32::from(output) / 7.5
+            } else {
+                0.0
+            };
+
+            if get_bit_status(panning, i as u8 + 4) {
+                left += analog;
+            }
+
+            if get_bit_status(panning, i as u8) {
+                right += analog;
+            }
+        }
+
+        let left_volume = f32::from((master_volume >> 4) & 0b111) + 1.0;
+        let right_volume = f32::from(master_volume & 0b111) + 1.0;
+
+        (left * left_volume / 32.0, right * right_volume / 32.0)
+    }
+}
+
+impl Bus {
+    pub fn step_apu(&mut self, t_cycles: u8) {
+        let div = self.get_timer_divider();
+        self.apu.step(t_cycles, div);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIV_FRAME_SEQUENCER: u8 = 1 << DIV_BIT_FRAME_SEQUENCER;
+
+    fn powered_on() -> APU {
+        let mut apu = APU::init(DEFAULT_SAMPLE_RATE);
+        apu.write(NR52, 0x80);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0xFF);
+        apu
+    }
+
+    /// Channel 2 at full volume with a 50% duty cycle and the length timer set to `length`
+    fn trigger_channel2(apu: &mut APU, length: u8) {
+        apu.write(NR21, 0x80 | length);
+        apu.write(NR22, 0xF0);
+        apu.write(NR23, 0x00);
+        apu.write(NR24, 0xC7);
+    }
+
+    fn clock_frame_sequencer(apu: &mut APU, times: usize) {
+        for _ in 0..times {
+            apu.step(4, DIV_FRAME_SEQUENCER);
+            apu.step(4, 0);
+        }
+    }
+
+    #[test]
+    fn frame_sequencer_is_clocked_by_div_falling_edge() {
+        let mut apu = powered_on();
+
+        apu.step(4, DIV_FRAME_SEQUENCER);
+        apu.step(4, DIV_FRAME_SEQUENCER);
+        assert_eq!(apu.frame_sequencer_step, 0);
+
+        apu.step(4, 0);
+        assert_eq!(apu.frame_sequencer_step, 1);
+
+        clock_frame_sequencer(&mut apu, 7);
+        assert_eq!(apu.frame_sequencer_step, 0);
+    }
+
+    #[test]
+    fn length_timer_disables_channel() {
+        let mut apu = powered_on();
+        trigger_channel2(&mut apu, 62);
+        assert_eq!(apu.read(NR52) & 0x0F, 0b0010);
+
+        // steps 0 and 2 clock the length timer
+        clock_frame_sequencer(&mut apu, 2);
+        assert_eq!(apu.read(NR52) & 0x0F, 0b0010);
+
+        clock_frame_sequencer(&mut apu, 1);
+        assert_eq!(apu.read(NR52) & 0x0F, 0);
+    }
+
+    #[test]
+    fn nr52_reports_power_and_channel_status() {
+        let mut apu = APU::init(DEFAULT_SAMPLE_RATE);
+        assert_eq!(apu.read(NR52), 0x70);
+
+        apu.write(NR52, 0x80);
+        trigger_channel2(&mut apu, 0);
+        assert_eq!(apu.read(NR52), 0xF2);
+
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+        assert_eq!(apu.read(NR22), 0x00);
+    }
+
+    #[test]
+    fn only_length_timers_are_writable_while_off() {
+        let mut apu = APU::init(DEFAULT_SAMPLE_RATE);
+        apu.write(NR21, 0xFF);
+        apu.write(NR22, 0xF0);
+
+        assert_eq!(apu.read(NR21), 0x3F);
+        assert_eq!(apu.read(NR22), 0x00);
+
+        // the length of 63 written while off expires with the first clock
+        apu.write(NR52, 0x80);
+        apu.write(NR22, 0xF0);
+        apu.write(NR24, 0xC0);
+        clock_frame_sequencer(&mut apu, 1);
+
+        assert_eq!(apu.read(NR52) & 0x0F, 0);
+    }
+
+    #[test]
+    fn samples_are_generated_at_the_sample_rate() {
+        let mut apu = APU::init(DEFAULT_SAMPLE_RATE);
+
+        // a 16th of a second
+        for _ in 0..CLOCK_SPEED / 4 / 16 {
+            apu.step(4, 0);
+        }
+
+        let samples = apu.drain_samples();
+        assert_eq!(
+            samples.len(),
+            DEFAULT_SAMPLE_RATE as usize / 16 * AUDIO_CHANNELS
+        );
+        assert!(samples.iter().all(|&sample| sample == 0.0));
+        assert!(apu.drain_samples().is_empty());
+    }
+
+    #[test]
+    fn samples_follow_channel_output() {
+        let mut apu = powered_on();
+        trigger_channel2(&mut apu, 0);
+
+        for _ in 0..CLOCK_SPEED / 4 / 100 {
+            apu.step(4, 0);
+        }
+
+        let samples = apu.drain_samples();
+        let (left, right): (Vec<f32>, Vec<f32>) = samples
+            .chunks_exact(AUDIO_CHANNELS)
+            .map(|pair| (pair[0], pair[1]))
+            .unzip();
+
+        assert_eq!(left, right);
+        assert!(left.iter().any(|&sample| sample > 0.0));
+        assert!(left.iter().any(|&sample| sample < 0.0));
+        assert!(left.iter().all(|sample| sample.abs() <= 1.0));
+    }
+}
@@ -0,0 +1,118 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use serde::{Deserialize, Serialize};
+
+const DIVISORS: [u16; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) struct NoiseChannel {
+    pub(super) enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    /// 15-bit linear feedback shift register
+    lfsr: u16,
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+        }
+    }
+
+    /// NR41: initial length timer
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length.load(byte & 0x3F);
+    }
+
+    /// NR42: volume envelope, disabling the DAC also disables the channel
+    pub(super) fn write_envelope(&mut self, byte: u8) {
+        self.envelope.write(byte);
+
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    /// NR43: clock shift, LFSR width and clock divider
+    pub(super) fn write_frequency(&mut self, byte: u8) {
+        self.clock_shift = byte >> 4;
+        self.short_mode = byte & 0b1000 != 0;
+        self.divisor_code = byte & 0b111;
+    }
+
+    /// NR44: length enable and trigger
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.length.enabled = byte & 0x40 != 0;
+
+        if byte & 0x80 != 0 {
+            self.enabled = self.dac_enabled();
+            self.timer = self.period();
+            self.lfsr = 0x7FFF;
+            self.length.trigger();
+            self.envelope.trigger();
+        }
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn period(&self) -> u32 {
+        u32::from(DIVISORS[usize::from(self.divisor_code)]) << self.clock_shift
+    }
+
+    pub(super) fn tick(&mut self, t_cycles: u8) {
+        let mut cycles = u32::from(t_cycles);
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.shift_lfsr();
+        }
+    }
+
+    fn shift_lfsr(&mut self) {
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+
+        if self.short_mode {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Current digital output in the range 0..=15
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 1 != 0 {
+            return 0;
+        }
+
+        self.envelope.volume
+    }
+}
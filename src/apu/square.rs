@@ -0,0 +1,286 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use serde::{Deserialize, Serialize};
+
+/// Waveforms for the duty cycles 12.5%, 25%, 50% and 75%, played from the most significant bit
+const DUTY_WAVEFORMS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const MAX_FREQUENCY: u16 = 2047;
+
+/// Frequency sweep unit, only present on channel 1 (NR10)
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    shadow_frequency: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn write(&mut self, byte: u8) {
+        self.period = (byte >> 4) & 0b111;
+        self.negate = byte & 0b1000 != 0;
+        self.shift = byte & 0b111;
+    }
+
+    fn reload_timer(&mut self) {
+        // a period of 0 is treated as 8 by the sweep timer
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow_frequency >> self.shift;
+
+        if self.negate {
+            self.shadow_frequency.wrapping_sub(delta)
+        } else {
+            self.shadow_frequency + delta
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) struct SquareChannel {
+    pub(super) enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u16,
+    pub(super) length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl SquareChannel {
+    pub(super) fn new(with_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::default(),
+            sweep: with_sweep.then(Sweep::default),
+        }
+    }
+
+    /// NR10: sweep pace, direction and individual step
+    pub(super) fn write_sweep(&mut self, byte: u8) {
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.write(byte);
+        }
+    }
+
+    /// NRx1: duty cycle and initial length timer
+    pub(super) fn write_length_duty(&mut self, byte: u8) {
+        self.duty = byte >> 6;
+        self.write_length(byte);
+    }
+
+    /// NRx1 without the duty cycle, which is all that is written while the APU is off
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length.load(byte & 0x3F);
+    }
+
+    /// NRx2: volume envelope, disabling the DAC also disables the channel
+    pub(super) fn write_envelope(&mut self, byte: u8) {
+        self.envelope.write(byte);
+
+        if !self.dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    /// NRx3: lower 8 bits of the period value
+    pub(super) fn write_frequency_low(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0x700) | u16::from(byte);
+    }
+
+    /// NRx4: upper 3 bits of the period value, length enable and trigger
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0xFF) | (u16::from(byte & 0b111) << 8);
+        self.length.enabled = byte & 0x40 != 0;
+
+        if byte & 0x80 != 0 {
+            self.trigger();
+        }
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.timer = self.period();
+        self.length.trigger();
+        self.envelope.trigger();
+
+        let frequency = self.frequency;
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+
+        sweep.shadow_frequency = frequency;
+        sweep.reload_timer();
+        sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+
+        if sweep.shift != 0 && sweep.next_frequency() > MAX_FREQUENCY {
+            self.enabled = false;
+        }
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    pub(super) fn tick(&mut self, t_cycles: u8) {
+        let mut cycles = u16::from(t_cycles);
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub(super) fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let new_frequency = sweep.next_frequency();
+
+        if new_frequency > MAX_FREQUENCY {
+            self.enabled = false;
+            return;
+        }
+
+        if sweep.shift != 0 {
+            sweep.shadow_frequency = new_frequency;
+            self.frequency = new_frequency;
+
+            // the overflow check is repeated with the new frequency, without applying it
+            if sweep.next_frequency() > MAX_FREQUENCY {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// Current digital output in the range 0..=15
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let waveform = DUTY_WAVEFORMS[usize::from(self.duty)];
+        let high = (waveform >> (7 - self.duty_step)) & 1;
+
+        high * self.envelope.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Channel 1 triggered at full volume with the sweep written to NR10
+    fn triggered_with_sweep(sweep: u8, frequency: u16) -> SquareChannel {
+        let mut channel = SquareChannel::new(true);
+        channel.write_sweep(sweep);
+        channel.write_envelope(0xF0);
+        channel.write_frequency_low(frequency as u8);
+        channel.write_control(0x80 | (frequency >> 8) as u8);
+        channel
+    }
+
+    #[test]
+    fn sweep_increases_frequency() {
+        let mut channel = triggered_with_sweep(0x11, 0x100);
+        channel.clock_sweep();
+
+        assert_eq!(channel.frequency, 0x180);
+        assert!(channel.enabled);
+    }
+
+    #[test]
+    fn sweep_decreases_frequency() {
+        let mut channel = triggered_with_sweep(0x19, 0x100);
+        channel.clock_sweep();
+
+        assert_eq!(channel.frequency, 0x80);
+    }
+
+    #[test]
+    fn sweep_waits_for_its_period() {
+        let mut channel = triggered_with_sweep(0x31, 0x100);
+
+        channel.clock_sweep();
+        channel.clock_sweep();
+        assert_eq!(channel.frequency, 0x100);
+
+        channel.clock_sweep();
+        assert_eq!(channel.frequency, 0x180);
+    }
+
+    #[test]
+    fn sweep_overflow_disables_channel() {
+        assert!(!triggered_with_sweep(0x11, 0x700).enabled);
+
+        // 0x500 + 0x280 fits, the check with the new frequency overflows
+        let mut channel = triggered_with_sweep(0x11, 0x500);
+        assert!(channel.enabled);
+
+        channel.clock_sweep();
+        assert_eq!(channel.frequency, 0x780);
+        assert!(!channel.enabled);
+    }
+
+    #[test]
+    fn output_follows_duty_cycle() {
+        let mut channel = SquareChannel::new(false);
+        channel.write_length_duty(0x80);
+        channel.write_envelope(0xA0);
+        // the shortest period of 4 T-cycles advances one step per tick
+        channel.write_frequency_low(0xFF);
+        channel.write_control(0x87);
+
+        let outputs: Vec<u8> = (0..8)
+            .map(|_| {
+                channel.tick(4);
+                channel.output()
+            })
+            .collect();
+
+        assert_eq!(outputs, [0, 0, 0, 0, 10, 10, 10, 10]);
+    }
+}
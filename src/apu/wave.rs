@@ -0,0 +1,145 @@
+use super::length_counter::LengthCounter;
+use serde::{Deserialize, Serialize};
+
+const WAVE_RAM_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) struct WaveChannel {
+    pub(super) enabled: bool,
+    dac_enabled: bool,
+    volume_shift: u8,
+    frequency: u16,
+    timer: u16,
+    /// Index of the current 4-bit sample, the wave RAM holds 32 of them
+    position: u8,
+    pub(super) length: LengthCounter,
+    wave_ram: [u8; WAVE_RAM_SIZE],
+}
+
+impl WaveChannel {
+    pub(super) fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            volume_shift: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            length: LengthCounter::new(256),
+            wave_ram: [0; WAVE_RAM_SIZE],
+        }
+    }
+
+    /// Resets all channel state on APU power off, wave RAM is not affected
+    pub(super) fn power_off(&self) -> Self {
+        Self {
+            wave_ram: self.wave_ram,
+            ..Self::new()
+        }
+    }
+
+    /// NR30: DAC enable
+    pub(super) fn write_dac(&mut self, byte: u8) {
+        self.dac_enabled = byte & 0x80 != 0;
+
+        if !self.dac_enabled {
+            self.enabled = false;
+        }
+    }
+
+    /// NR31: initial length timer
+    pub(super) fn write_length(&mut self, byte: u8) {
+        self.length.load(byte);
+    }
+
+    /// NR32: output level
+    pub(super) fn write_output_level(&mut self, byte: u8) {
+        self.volume_shift = match (byte >> 5) & 0b11 {
+            0 => 4,
+            1 => 0,
+            2 => 1,
+            _ => 2,
+        };
+    }
+
+    /// NR33: lower 8 bits of the period value
+    pub(super) fn write_frequency_low(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0x700) | u16::from(byte);
+    }
+
+    /// NR34: upper 3 bits of the period value, length enable and trigger
+    pub(super) fn write_control(&mut self, byte: u8) {
+        self.frequency = (self.frequency & 0xFF) | (u16::from(byte & 0b111) << 8);
+        self.length.enabled = byte & 0x40 != 0;
+
+        if byte & 0x80 != 0 {
+            self.enabled = self.dac_enabled;
+            self.timer = self.period();
+            self.position = 0;
+            self.length.trigger();
+        }
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    /// While the channel is playing, the CPU can only access the byte currently read by the
+    /// channel
+    pub(super) fn read_wave_ram(&self, index: usize) -> u8 {
+        if self.enabled {
+            self.wave_ram[usize::from(self.position / 2)]
+        } else {
+            self.wave_ram[index]
+        }
+    }
+
+    pub(super) fn write_wave_ram(&mut self, index: usize, byte: u8) {
+        if self.enabled {
+            self.wave_ram[usize::from(self.position / 2)] = byte;
+        } else {
+            self.wave_ram[index] = byte;
+        }
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    pub(super) fn tick(&mut self, t_cycles: u8) {
+        let mut cycles = u16::from(t_cycles);
+
+        while cycles > 0 {
+            if self.timer > cycles {
+                self.timer -= cycles;
+                return;
+            }
+
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Current digital output in the range 0..=15
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let byte = self.wave_ram[usize::from(self.position / 2)];
+        let sample = if self.position.is_multiple_of(2) {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+
+        sample >> self.volume_shift
+    }
+}
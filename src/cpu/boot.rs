@@ -1,6 +1,10 @@
+use crate::apu::{
+    NR10, NR11, NR12, NR13, NR14, NR21, NR22, NR23, NR24, NR30, NR31, NR32, NR33, NR34, NR41, NR42,
+    NR43, NR44, NR50, NR51, NR52,
+};
 use crate::cpu::CPU;
 use crate::memory::bus::{
-    INTERRUPT_ENABLE, INTERRUPT_REQUESTS, JOYP, LCD_Y_COMPARE, SCROLL_X, SCROLL_Y,
+    BG_PALETTE, INTERRUPT_ENABLE, INTERRUPT_REQUESTS, JOYP, LCD_Y_COMPARE, SCROLL_X, SCROLL_Y,
     SERIAL_TRANSFER_CONTROL, SERIAL_TRANSFER_DATA, TIMER_CONTROL, TIMER_COUNTER, TIMER_MODULO,
     WINDOW_X, WINDOW_Y,
 };
@@ -8,9 +12,6 @@ use crate::memory::bus::{
 impl CPU {
     pub(super) fn load_boot_rom(&mut self, boot_rom: &[u8]) {
         self.bus.write_boot_rom(boot_rom);
-
-        // Stub JOYPAD Status to 0xCF until proper implementation
-        self.bus.write_byte(JOYP, 0xCF);
     }
 
     /// Set state according to https://gbdev.io/pandocs/Power_Up_Sequence.html
@@ -37,36 +38,35 @@ impl CPU {
         self.bus.write_byte(SERIAL_TRANSFER_DATA, 0x00);
         self.bus.write_byte(SERIAL_TRANSFER_CONTROL, 0x7E);
 
-        // DIV
-        // self.bus.write_byte(0xFF04, 0xAB);
+        self.bus.set_timer_divider(0xAB);
 
         self.bus.write_byte(TIMER_COUNTER, 0x00);
         self.bus.write_byte(TIMER_MODULO, 0x00);
         self.bus.write_byte(TIMER_CONTROL, 0xF8);
         self.bus.write_byte(INTERRUPT_REQUESTS, 0xE1);
 
-        // NR
-        // self.bus.write_byte(0xFF10, 0x80);
-        // self.bus.write_byte(0xFF11, 0xBF);
-        // self.bus.write_byte(0xFF12, 0xF3);
-        // self.bus.write_byte(0xFF13, 0xFF);
-        // self.bus.write_byte(0xFF14, 0xBF);
-        // self.bus.write_byte(0xFF16, 0x3F);
-        // self.bus.write_byte(0xFF17, 0x00);
-        // self.bus.write_byte(0xFF18, 0xFF);
-        // self.bus.write_byte(0xFF19, 0xBF);
-        // self.bus.write_byte(0xFF1A, 0x7F);
-        // self.bus.write_byte(0xFF1B, 0xFF);
-        // self.bus.write_byte(0xFF1C, 0x9F);
-        // self.bus.write_byte(0xFF1D, 0xFF);
-        // self.bus.write_byte(0xFF1E, 0xBF);
-        // self.bus.write_byte(0xFF20, 0xFF);
-        // self.bus.write_byte(0xFF21, 0x00);
-        // self.bus.write_byte(0xFF22, 0x00);
-        // self.bus.write_byte(0xFF23, 0xBF);
-        // self.bus.write_byte(0xFF24, 0x77);
-        // self.bus.write_byte(0xFF25, 0xF3);
-        // self.bus.write_byte(0xFF26, 0xF1);
+        // the APU has to be powered on first, otherwise the other register writes are ignored
+        self.bus.write_byte(NR52, 0xF1);
+        self.bus.write_byte(NR10, 0x80);
+        self.bus.write_byte(NR11, 0xBF);
+        self.bus.write_byte(NR12, 0xF3);
+        self.bus.write_byte(NR13, 0xFF);
+        self.bus.write_byte(NR14, 0xBF);
+        self.bus.write_byte(NR21, 0x3F);
+        self.bus.write_byte(NR22, 0x00);
+        self.bus.write_byte(NR23, 0xFF);
+        self.bus.write_byte(NR24, 0xBF);
+        self.bus.write_byte(NR30, 0x7F);
+        self.bus.write_byte(NR31, 0xFF);
+        self.bus.write_byte(NR32, 0x9F);
+        self.bus.write_byte(NR33, 0xFF);
+        self.bus.write_byte(NR34, 0xBF);
+        self.bus.write_byte(NR41, 0xFF);
+        self.bus.write_byte(NR42, 0x00);
+        self.bus.write_byte(NR43, 0x00);
+        self.bus.write_byte(NR44, 0xBF);
+        self.bus.write_byte(NR50, 0x77);
+        self.bus.write_byte(NR51, 0xF3);
 
         self.bus.set_lcd_control(0x91);
         self.bus.set_lcd_stat(0x85);
@@ -78,10 +78,8 @@ impl CPU {
         // DMA
         self.bus.write_byte(0xFF46, 0xFF);
 
-        // BGP
-        // self.bus.write_byte(0xFF47, 0xFC);
-        // OBP0
-        // OBP1
+        self.bus.write_byte(BG_PALETTE, 0xFC);
+        // OBP0 and OBP1 are left uninitialized by the DMG boot rom
 
         self.bus.write_byte(WINDOW_Y, 0x00);
         self.bus.write_byte(WINDOW_X, 0x00);
@@ -1,19 +1,24 @@
-use super::dma::DmaState;
+use super::dma::Dma;
 use super::instructions::Executable;
 use super::interrupts::{HaltState, InterruptState};
 use super::opcodes::get_instruction;
 use super::registers::*;
 use super::timers::Clock;
-use crate::memory::bus::{Bus, DMA_START, SERIAL_TRANSFER_CONTROL, SERIAL_TRANSFER_DATA};
+use crate::memory::bus::{Bus, DMA_START};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, Copy)]
+/// `LD B,B` has no effect and is used by test roms as a software breakpoint
+const BREAKPOINT_OPCODE: u8 = 0x40;
+
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(crate) struct InstructionData {
     pub(crate) opcode: u8,
     pub(super) param1: u8,
     pub(super) param2: u8,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CPU {
     pub(crate) registers: Registers,
     pub(crate) bus: Bus,
@@ -22,97 +27,148 @@ pub struct CPU {
     pub(crate) halt_state: HaltState,
     /// Timers
     pub(super) clock: Clock,
-    pub(super) last_timer_update: u64,
     /// Interrupt handling
     pub(crate) interrupt_state: InterruptState,
-    /// DMA State
-    pub(crate) dma_state: DmaState,
+    /// OAM DMA transfer
+    pub(crate) dma: Dma,
+    /// M-cycles of the current step the hardware was already advanced by
+    #[serde(skip)]
+    step_cycles: u8,
 }
 
 impl CPU {
-    fn from_cardridge(cartridge_contents: &[u8]) -> Self {
-        Self {
+    fn from_cardridge(cartridge_contents: &[u8]) -> Result<Self> {
+        Ok(Self {
             registers: Registers::default(),
-            bus: Bus::from_cartridge(cartridge_contents),
+            bus: Bus::from_cartridge(cartridge_contents)?,
             current_instruction: InstructionData::default(),
             halt_state: HaltState::default(),
             clock: Clock::default(),
-            last_timer_update: 0,
             interrupt_state: InterruptState::default(),
-            dma_state: DmaState::Inactive,
-        }
+            dma: Dma::default(),
+            step_cycles: 0,
+        })
     }
 
-    pub fn init(boot_rom: Option<&[u8]>, cartridge_contents: &[u8]) -> Self {
-        let mut cpu = Self::from_cardridge(cartridge_contents);
+    pub fn init(boot_rom: Option<&[u8]>, cartridge_contents: &[u8]) -> Result<Self> {
+        let mut cpu = Self::from_cardridge(cartridge_contents)?;
 
         match boot_rom {
             Some(rom) => cpu.load_boot_rom(rom),
             None => cpu.init_boot_handoff(),
         }
 
-        cpu
+        Ok(cpu)
+    }
+
+    /// Reads without taking an M-cycle. CPU reads conflicting with a running OAM DMA see the byte
+    /// being transferred instead.
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        self.dma
+            .conflicting_read(address)
+            .unwrap_or_else(|| self.bus.read_byte(address))
     }
 
-    pub fn read_byte(&self, address: u16) -> u8 {
-        self.bus.read_byte(address)
+    /// Memory accesses of instructions take one M-cycle each, during which the DMA and the timer
+    /// advance
+    pub fn read_byte(&mut self, address: u16) -> u8 {
+        self.tick_cycle();
+        self.peek_byte(address)
     }
 
-    // TODO: wrapper for DMA detection
     pub fn write_byte(&mut self, address: u16, byte: u8) {
+        self.tick_cycle();
+
+        if self.dma.blocks_write(address) {
+            return;
+        }
+
         self.bus.write_byte(address, byte);
 
         if address == DMA_START {
-            self.dma_state = DmaState::init_dma_transfer(byte);
+            self.dma.request(byte);
         }
     }
 
-    // TODO: handle out of bound fetch
+    /// Reads the opcode in its own M-cycle
     fn fetch(&mut self) -> InstructionData {
-        match self.halt_state {
-            HaltState::HaltBug => {
-                self.halt_state = HaltState::NotHalted;
-
-                InstructionData {
-                    opcode: self.bus.read_byte(self.registers.pc),
-                    param1: self.bus.read_byte(self.registers.pc),
-                    param2: self.bus.read_byte(self.registers.pc + 1),
-                }
-            }
-            _ => InstructionData {
-                opcode: self.bus.read_byte(self.registers.pc),
-                param1: self.bus.read_byte(self.registers.pc + 1),
-                param2: self.bus.read_byte(self.registers.pc + 2),
-            },
+        InstructionData {
+            opcode: self.read_byte(self.registers.pc),
+            ..InstructionData::default()
         }
     }
 
-    pub fn step(&mut self) -> u8 {
-        if !matches!(self.dma_state, DmaState::Inactive) {
-            self.dma_state = self.dma_state.step(&mut self.bus);
+    /// Reads the operands of the fetched opcode, each in its own M-cycle
+    fn fetch_operands(&mut self, bytes: u16) {
+        // the halt bug fails to increment PC after the opcode, which is read again as operand
+        let address = match self.halt_state {
+            HaltState::HaltBug => self.registers.pc,
+            _ => self.registers.pc.wrapping_add(1),
+        };
+
+        if bytes > 1 {
+            self.current_instruction.param1 = self.read_byte(address);
+        }
 
+        if bytes > 2 {
+            self.current_instruction.param2 = self.read_byte(address.wrapping_add(1));
+        }
+    }
+
+    /// Advances the OAM DMA and the timer by one M-cycle of the current step
+    pub(super) fn tick_cycle(&mut self) {
+        self.dma.step_cycle(&mut self.bus);
+        self.clock.increment(4);
+        self.bus.tick_timer(4);
+        self.step_cycles += 1;
+    }
+
+    /// Advances the hardware by the M-cycles of the current step without memory access
+    fn catch_up(&mut self, t_cycles: u8) {
+        while self.step_cycles < t_cycles / 4 {
+            self.tick_cycle();
+        }
+    }
+
+    /// The OAM DMA and the timer run alongside the CPU, advancing with every memory access
+    pub fn step(&mut self) -> u8 {
+        // the system counter does not advance in STOP mode
+        if self.is_stopped() && self.update_stop_state() {
             return 4;
         }
 
+        self.step_cycles = 0;
+
+        let cycles = self.step_instruction();
+        self.catch_up(cycles);
+
+        cycles
+    }
+
+    fn step_instruction(&mut self) -> u8 {
         self.current_instruction = self.fetch();
 
         if matches!(self.halt_state, HaltState::Halted) {
             // keep incrementing timers during halted state by base cycles of 4
-            let cycles = self.handle_halted_interrupts() + 4;
-            self.update_timers(cycles);
-            return cycles;
+            return self.handle_halted_interrupts() + 4;
         }
 
-        let (instruction, bytes) = get_instruction(&self.current_instruction);
+        // the length only depends on the opcode, the operands are decoded once they are read
+        let (_, bytes) = get_instruction(&self.current_instruction);
+        self.fetch_operands(bytes);
+        let (instruction, _) = get_instruction(&self.current_instruction);
+
+        self.registers.pc = self.registers.pc.wrapping_add(bytes);
 
-        if !matches!(self.halt_state, HaltState::HaltBug) {
-            self.registers.pc += bytes;
+        if matches!(self.halt_state, HaltState::HaltBug) {
+            self.halt_state = HaltState::NotHalted;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
         }
 
         // self.log_state();
 
         let instruction_cycles = instruction.execute(self);
-        self.update_timers(instruction_cycles);
+        self.catch_up(instruction_cycles);
 
         // Handle Interrupt Enable requested by EI instruction, which is delayed by one instruction
         if matches!(self.interrupt_state, InterruptState::EnableRequested)
@@ -121,23 +177,13 @@ impl CPU {
             self.interrupt_state = InterruptState::Enabled;
         }
 
-        let interrupt_cycles = self.handle_interrupts();
-        self.update_timers(interrupt_cycles);
-
-        self.print_serial_output();
-
-        instruction_cycles + interrupt_cycles
+        instruction_cycles + self.handle_interrupts()
     }
 
-    fn print_serial_output(&mut self) {
-        // test rom serial output
-        if self.bus.read_byte(SERIAL_TRANSFER_CONTROL) == 0x81 {
-            let character = self.bus.read_byte(SERIAL_TRANSFER_DATA);
-            if character != 0x00 {
-                log::info!("{}", character as char);
-                self.bus.write_byte(SERIAL_TRANSFER_DATA, 0x00);
-            }
-        }
+    /// Whether the last step executed the `LD B,B` breakpoint
+    pub fn hit_breakpoint(&self) -> bool {
+        self.current_instruction.opcode == BREAKPOINT_OPCODE
+            && !matches!(self.halt_state, HaltState::Halted)
     }
 
     #[allow(unused)]
@@ -1,35 +1,104 @@
-use crate::memory::bus::{Bus, OAM_START};
+use crate::memory::bus::{Bus, OAM_END, OAM_START, VRAM_END, VRAM_START};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+/// Number of bytes copied to OAM, one per M-cycle
+const DMA_TRANSFER_LENGTH: u16 = 160;
+/// M-cycles between the write to FF46 and the first byte being copied
+const DMA_STARTUP_DELAY: u8 = 1;
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
 pub(crate) enum DmaState {
-    Active { src: u16, index: u16 },
+    Active {
+        src: u16,
+        index: u16,
+    },
+    #[default]
     Inactive,
 }
 
-impl DmaState {
-    pub(super) fn init_dma_transfer(byte: u8) -> Self {
-        DmaState::Active {
-            src: (byte as u16) << 8,
-            index: 0,
+/// The DMA and the CPU share the external bus (ROM, SRAM and WRAM) and the video bus (VRAM). While
+/// a transfer is running the CPU can't access the bus the DMA is reading from, nor OAM.
+#[derive(PartialEq)]
+enum MemoryBus {
+    External,
+    Video,
+}
+
+fn memory_bus(address: u16) -> Option<MemoryBus> {
+    match address {
+        VRAM_START..=VRAM_END => Some(MemoryBus::Video),
+        0x0000..=0x7FFF | 0xA000..=0xFDFF => Some(MemoryBus::External),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub(crate) struct Dma {
+    state: DmaState,
+    /// Transfer waiting for its startup delay, a running transfer continues until it starts
+    pending: Option<(u16, u8)>,
+    /// Byte the transfer read last, which is what the CPU sees on conflicting reads
+    current_byte: u8,
+}
+
+impl Dma {
+    /// (Re)starts a transfer after the startup delay. Sources above 0xDF00 are mirrored to WRAM,
+    /// like the echo RAM.
+    pub(super) fn request(&mut self, byte: u8) {
+        let byte = if byte > 0xDF { byte - 0x20 } else { byte };
+
+        self.pending = Some((u16::from(byte) << 8, DMA_STARTUP_DELAY));
+    }
+
+    /// Advances the DMA by one M-cycle
+    pub(super) fn step_cycle(&mut self, bus: &mut Bus) {
+        match self.pending {
+            Some((src, 0)) => {
+                self.state = DmaState::Active { src, index: 0 };
+                self.pending = None;
+            }
+            Some((src, delay)) => self.pending = Some((src, delay - 1)),
+            None => {}
+        }
+
+        let DmaState::Active { src, index } = self.state else {
+            return;
+        };
+
+        // the bus stays blocked during the M-cycle the last byte is copied in
+        if index == DMA_TRANSFER_LENGTH {
+            self.state = DmaState::Inactive;
+            return;
         }
+
+        // OAM is written directly, the PPU mode does not block the DMA
+        self.current_byte = bus.read_byte(src + index);
+        bus.oam.write(index, self.current_byte);
+
+        self.state = DmaState::Active {
+            src,
+            index: index + 1,
+        };
     }
 
-    pub(super) fn step(self, bus: &mut Bus) -> Self {
-        match self {
-            DmaState::Inactive => DmaState::Inactive,
-            DmaState::Active { src, index } => {
-                let byte = bus.read_byte(src + index);
-                bus.write_byte(OAM_START + index, byte);
-
-                if index == 159 {
-                    DmaState::Inactive
-                } else {
-                    DmaState::Active {
-                        src: src,
-                        index: index + 1,
-                    }
-                }
+    /// Returns the byte the CPU reads instead of `address` if the access conflicts with a running
+    /// transfer. OAM reads return 0xFF.
+    pub(super) fn conflicting_read(&self, address: u16) -> Option<u8> {
+        let DmaState::Active { src, .. } = self.state else {
+            return None;
+        };
+
+        match address {
+            OAM_START..=OAM_END => Some(0xFF),
+            _ if memory_bus(address).is_some() && memory_bus(address) == memory_bus(src) => {
+                Some(self.current_byte)
             }
+            _ => None,
         }
     }
+
+    /// CPU writes to OAM or to the bus used by a running transfer are lost
+    pub(super) fn blocks_write(&self, address: u16) -> bool {
+        self.conflicting_read(address).is_some()
+    }
 }
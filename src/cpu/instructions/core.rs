@@ -656,6 +656,7 @@ impl Executable for RST {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum RET {
     RET,
     Conditional(FlagCondition),
@@ -844,8 +845,9 @@ impl Executable for NOP {
 pub(crate) struct STOP(pub(crate) u8);
 
 impl Executable for STOP {
-    fn execute(&self, _cpu: &mut CPU) -> u8 {
-        // TODO: implement
+    fn execute(&self, cpu: &mut CPU) -> u8 {
+        cpu.enter_stop_mode();
+
         4
     }
 }
@@ -899,7 +901,7 @@ fn half_carry_set_add_u16(a: u16, b: u16) -> bool {
 }
 
 impl CPU {
-    pub fn read_byte_at_offset(&self, offset: u8) -> u8 {
+    pub fn read_byte_at_offset(&mut self, offset: u8) -> u8 {
         let address = 0xFF00 + u16::from(offset);
         self.read_byte(address)
     }
@@ -915,14 +917,16 @@ impl CPU {
         self.write_byte(address + 1, msb);
     }
 
-    pub fn read_word(&self, address: u16) -> u16 {
+    pub fn read_word(&mut self, address: u16) -> u16 {
         let lsb = self.read_byte(address);
         let msb = self.read_byte(address + 1);
 
         u16::from_le_bytes([lsb, msb])
     }
 
+    /// SP is decremented in an internal M-cycle before the writes
     fn push_to_stack(&mut self, value: u16) {
+        self.tick_cycle();
         self.registers.sp = self.registers.sp.wrapping_sub(2);
         self.write_word(self.registers.sp, value);
     }
@@ -933,7 +937,7 @@ impl CPU {
         result
     }
 
-    fn read_bytetarget(&self, target: &ByteTarget) -> u8 {
+    fn read_bytetarget(&mut self, target: &ByteTarget) -> u8 {
         match target {
             ByteTarget::Constant(value) => *value,
             ByteTarget::Register8(register) => self.read_r8(register),
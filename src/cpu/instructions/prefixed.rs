@@ -331,7 +331,8 @@ impl Executable for RES {
                 8
             }
             RES::HLAddress(bit) => {
-                cpu.write_hl_ptr(cpu.read_hl_ptr() & !bit.as_bit_mask());
+                let value = cpu.read_hl_ptr();
+                cpu.write_hl_ptr(value & !bit.as_bit_mask());
                 16
             }
         }
@@ -351,7 +352,8 @@ impl Executable for SET {
                 8
             }
             SET::HLAddress(bit) => {
-                cpu.write_hl_ptr(cpu.read_hl_ptr() | bit.as_bit_mask());
+                let value = cpu.read_hl_ptr();
+                cpu.write_hl_ptr(value | bit.as_bit_mask());
                 16
             }
         }
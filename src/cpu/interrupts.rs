@@ -1,5 +1,6 @@
 use crate::cpu::CPU;
-use crate::memory::bus::{Bus, INTERRUPT_ENABLE, INTERRUPT_REQUESTS};
+use crate::memory::bus::{Bus, INTERRUPT_ENABLE, INTERRUPT_REQUESTS, TIMER_DIVIDER};
+use serde::{Deserialize, Serialize};
 
 const INTERRUPT_VBLANK_BIT: u8 = 0b1;
 const INTERRUPT_STAT_BIT: u8 = 0b10;
@@ -10,7 +11,7 @@ const INTERRUPT_JOYPAD_BIT: u8 = 0b10000;
 const INTERRUPT_HANDLER_CYCLES: u8 = 20;
 const INTERRUPT_IGNORE_CYCLES: u8 = 0;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(crate) enum InterruptState {
     Enabled,
     #[default]
@@ -18,12 +19,15 @@ pub(crate) enum InterruptState {
     EnableRequested,
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(crate) enum HaltState {
     #[default]
     NotHalted,
     Halted,
     HaltBug,
+    /// Low-power mode entered by STOP, the CPU, timer and PPU are halted until a joypad line goes
+    /// low
+    Stopped,
 }
 
 #[derive(Clone, Copy)]
@@ -48,12 +52,10 @@ impl Bus {
         self.enable_interrupt_request(INTERRUPT_TIMER_BIT)
     }
 
-    #[allow(unused)]
     pub fn request_serial_interrupt(&mut self) {
         self.enable_interrupt_request(INTERRUPT_SERIAL_BIT)
     }
 
-    #[allow(unused)]
     pub fn request_joypad_interrupt(&mut self) {
         self.enable_interrupt_request(INTERRUPT_JOYPAD_BIT)
     }
@@ -117,6 +119,38 @@ impl CPU {
         };
     }
 
+    /// Called by the STOP instruction, see https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
+    pub(super) fn enter_stop_mode(&mut self) {
+        // with a button held, STOP is skipped and behaves like HALT or a 1-byte NOP
+        if self.bus.joypad_line_low() {
+            if self.bus.is_interrupt_pending() {
+                self.registers.pc -= 1;
+            } else {
+                self.halt_state = HaltState::Halted;
+            }
+
+            return;
+        }
+
+        // TODO: CGB speed switch, a switch prepared through KEY1 happens here instead of stopping
+
+        self.bus.write_byte(TIMER_DIVIDER, 0);
+        self.halt_state = HaltState::Stopped;
+    }
+
+    /// Leaves STOP mode once a joypad line goes low. Returns whether the CPU is still stopped.
+    pub(super) fn update_stop_state(&mut self) -> bool {
+        if self.bus.joypad_line_low() {
+            self.halt_state = HaltState::NotHalted;
+        }
+
+        self.is_stopped()
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        matches!(self.halt_state, HaltState::Stopped)
+    }
+
     pub(super) fn handle_interrupts(&mut self) -> u8 {
         if !matches!(self.interrupt_state, InterruptState::Enabled) {
             return INTERRUPT_IGNORE_CYCLES;
@@ -152,6 +186,8 @@ impl CPU {
         self.interrupt_state = InterruptState::Disabled;
         self.bus.clear_interrupt_source(&interrupt_source);
 
+        // two wait states precede the push, the second one decrements SP
+        self.tick_cycle();
         self.call_address(interrupt_source as u16);
 
         INTERRUPT_HANDLER_CYCLES
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
-/// Defines common register groups found in instructions and provides some helper functions
 use crate::cpu::CPU;
+/// Defines common register groups found in instructions and provides some helper functions
+use serde::{Deserialize, Serialize};
 use std::convert::From;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(crate) struct Flags {
     pub(crate) zero: bool,
     pub(crate) negative: bool,
@@ -41,7 +42,7 @@ impl From<u8> for Flags {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub(crate) struct Registers {
     pub(crate) a: u8,
     pub(crate) b: u8,
@@ -125,7 +126,7 @@ impl CPU {
         [self.registers.h, self.registers.l] = value.to_be_bytes();
     }
 
-    pub fn read_hl_ptr(&self) -> u8 {
+    pub fn read_hl_ptr(&mut self) -> u8 {
         self.read_byte(self.read_hl())
     }
 
@@ -193,7 +194,7 @@ impl CPU {
     pub(crate) fn read_from(&mut self, register: &R16Mem) -> u8 {
         let address = self.read_r16m(register);
 
-        self.bus.read_byte(address)
+        self.read_byte(address)
     }
 
     pub(crate) fn store_at(&mut self, register: &R16Mem, value: u8) {
@@ -1,9 +1,20 @@
 #![allow(unused)]
 use crate::cpu::CPU;
-use crate::graphics::{App, PixelData, LCD_HEIGHT, LCD_WIDTH, PPU};
-use anyhow::Result;
-use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+#[cfg(feature = "window")]
+use crate::graphics::App;
+use crate::graphics::{PixelData, LCD_HEIGHT, LCD_WIDTH, PPU};
+use crate::headless::{HeadlessConfig, HeadlessResult, HeadlessRun};
+use crate::memory::cartridge::{CartridgeEvent, RtcClock};
+use crate::memory::joypad::JoypadEvent;
+use crate::memory::serial::{CaptureBuffer, SerialTee, SharedSerialDevice};
+use crate::movie::{MovieMode, MovieSession};
+use crate::rewind::{RewindBuffer, REWIND_INTERVAL};
+use crate::save_state::save_state_path;
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::thread::JoinHandle;
 use std::{
     sync::atomic::{AtomicBool, Ordering},
@@ -12,7 +23,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct EmulatorState {
     pub cpu: CPU,
     pub ppu: PPU,
@@ -31,102 +42,438 @@ impl EmulatorState {
     pub fn step(&mut self) -> u8 {
         let cycles = self.cpu.step();
 
+        self.cpu.bus.step_cartridge(cycles);
+        self.cpu.bus.step_serial_device(cycles);
+
+        // STOP halts the PPU and APU along with the CPU
+        if self.cpu.is_stopped() {
+            self.framebuffer = None;
+            return cycles;
+        }
+
+        self.cpu.bus.step_apu(cycles);
         self.framebuffer = self.ppu.step(cycles, &mut self.cpu.bus);
 
         cycles
     }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    /// Collects the audio generated since the last call as interleaved stereo samples
+    pub fn drain_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.drain_samples()
+    }
+
+    /// Collects the cartridge events like rumble motor changes since the last call
+    pub fn drain_cartridge_events(&mut self) -> Vec<CartridgeEvent> {
+        self.cpu.bus.drain_cartridge_events()
+    }
+
+    pub fn handle_joypad_event(&mut self, event: JoypadEvent) {
+        self.cpu.bus.handle_joypad_event(event);
+    }
+
+    /// Returns the battery backed cartridge RAM if it changed since the last save
+    fn take_dirty_battery_save(&mut self) -> Option<Vec<u8>> {
+        let bus = &mut self.cpu.bus;
+
+        (bus.has_battery() && bus.is_cartridge_ram_dirty()).then(|| bus.battery_save())
+    }
+}
+
+/// Requests sent from the window thread to the emulation thread
+#[derive(Clone, Copy, Debug)]
+pub enum EmulatorCommand {
+    Joypad(JoypadEvent),
+    SaveState(u8),
+    LoadState(u8),
+    /// Steps backwards through the rewind snapshots while active
+    Rewind(bool),
+}
+
+/// Battery backed RAM is stored next to the rom, in the `.sav` format used by other emulators
+pub fn battery_save_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+/// Everything the emulator is started with besides the cartridge
+#[derive(Default)]
+pub struct EmulatorConfig<'a> {
+    /// Runs the boot rom instead of starting in the hand-off state
+    pub boot_contents: Option<&'a [u8]>,
+    pub paused: bool,
+    pub rtc_clock: RtcClock,
+    /// Battery saves and save states are stored next to the rom
+    pub rom_path: Option<&'a Path>,
+    pub save_contents: Option<&'a [u8]>,
+    /// Device attached to the link port, the serial output is only logged otherwise
+    pub serial_device: Option<SharedSerialDevice>,
+    /// Runs without a window instead
+    pub headless: Option<HeadlessConfig>,
+    pub movie: Option<MovieMode>,
 }
 
 pub struct Emulator {
     /// Transferable Emulator State
     pub state: Arc<RwLock<EmulatorState>>,
     /// Emulation thread & synchronization flags
-    pub emulation_thread: JoinHandle<()>,
+    pub emulation_thread: JoinHandle<Option<Result<HeadlessResult>>>,
     pub terminated: Arc<AtomicBool>,
     pub paused: Arc<AtomicBool>,
-    /// Rendering
-    app: App,
+    /// Battery backed cartridge RAM is persisted to this file
+    save_path: Option<PathBuf>,
+    /// Rendering, `None` in headless mode
+    #[cfg(feature = "window")]
+    app: Option<App>,
 }
 
 impl Emulator {
-    pub fn init(
-        boot_contents: Option<&[u8]>,
-        cartridge_contents: &[u8],
-        paused: bool,
-    ) -> Result<Self> {
-        let cpu = CPU::init(boot_contents, cartridge_contents);
-
-        let state = Arc::new(RwLock::new(EmulatorState::init(cpu)));
+    pub fn init(cartridge_contents: &[u8], config: EmulatorConfig) -> Result<Self> {
+        let EmulatorConfig {
+            boot_contents,
+            paused,
+            rtc_clock,
+            rom_path,
+            save_contents,
+            serial_device,
+            headless,
+            movie,
+        } = config;
+
+        if cfg!(not(feature = "window")) && headless.is_none() {
+            bail!("Built without the window feature, only headless mode is available");
+        }
+
+        let mut cpu = CPU::init(boot_contents, cartridge_contents)?;
+        cpu.bus.set_rtc_clock(rtc_clock);
+
+        // serial output is logged, which is how test roms report results. A connected device
+        // receives the output as well.
+        let serial_output = CaptureBuffer::default();
+
+        match serial_device {
+            Some(serial_device) => cpu.bus.connect_serial(Arc::new(Mutex::new(SerialTee::new(
+                serial_device,
+                serial_output.clone(),
+            )))),
+            None => cpu
+                .bus
+                .connect_serial(Arc::new(Mutex::new(serial_output.clone()))),
+        }
+
+        // cartridges without battery have nothing to persist, movies are played back without
+        // touching the battery save
+        let playing_movie = matches!(movie, Some(MovieMode::Play(_)));
+        let save_path = rom_path
+            .filter(|_| cpu.bus.has_battery() && !playing_movie)
+            .map(battery_save_path);
+
+        let mut battery_loaded = false;
+
+        if let (Some(save_path), Some(save_contents)) = (&save_path, save_contents) {
+            cpu.bus
+                .load_battery_save(save_contents)
+                .with_context(|| format!("Failed to load {}", save_path.display()))?;
+            battery_loaded = true;
+        }
+
+        let mut state = EmulatorState::init(cpu);
+
+        // a loaded battery save is part of the start state of a recording
+        let movie = movie
+            .map(|mode| MovieSession::start(mode, &mut state, !battery_loaded))
+            .transpose()?;
+
+        let state = Arc::new(RwLock::new(state));
         let terminated = Arc::new(AtomicBool::new(false));
         let paused = Arc::new(AtomicBool::new(paused));
 
         let (frame_sender, frame_receiver) = bounded(3);
+        let (command_sender, command_receiver) = unbounded();
 
-        let emulation_thread = start_emulation(
-            state.clone(),
-            terminated.clone(),
-            paused.clone(),
-            frame_sender,
-        );
+        #[cfg(feature = "window")]
+        let app = headless
+            .is_none()
+            .then(|| App::init(terminated.clone(), frame_receiver, command_sender));
+
+        // without a window, frames are not sent anywhere and the emulation is not paced
+        let frame_sender = headless.is_none().then_some(frame_sender);
 
-        let app = App::init(terminated.clone(), frame_receiver);
+        let emulation_thread = EmulationThread {
+            state: state.clone(),
+            terminated: terminated.clone(),
+            paused: paused.clone(),
+            frame_sender,
+            command_receiver,
+            rom_path: rom_path.map(Path::to_path_buf),
+            save_path: save_path.clone(),
+            serial_output,
+            headless: headless.map(HeadlessRun::new),
+            movie,
+        }
+        .spawn();
 
         let emulator = Emulator {
+            #[cfg(feature = "window")]
             app,
             state,
             emulation_thread,
             terminated,
             paused,
+            save_path,
         };
 
         Ok(emulator)
     }
 
+    /// Runs the window until it is closed, returns immediately in headless mode
     pub fn start(&mut self) {
-        self.app.run();
+        #[cfg(feature = "window")]
+        if let Some(app) = &mut self.app {
+            app.run();
+        }
     }
+
+    /// Waits for the emulation thread to finish and writes the battery backed cartridge RAM.
+    /// Returns the result of a headless run.
+    pub fn shutdown(self) -> Result<Option<HeadlessResult>> {
+        let headless_result = self.emulation_thread.join().unwrap();
+
+        if let Some(save_path) = &self.save_path {
+            let contents = self.state.write().unwrap().cpu.bus.battery_save();
+            write_battery_save(save_path, &contents)?;
+        }
+
+        headless_result.transpose()
+    }
+}
+
+fn write_battery_save(save_path: &Path, contents: &[u8]) -> Result<()> {
+    fs::write(save_path, contents)
+        .with_context(|| format!("Failed to write {}", save_path.display()))?;
+
+    log::info!("Saved cartridge RAM to {}", save_path.display());
+
+    Ok(())
 }
 
-const CLOCK_SPEED: u32 = 4_194_304;
+pub const CLOCK_SPEED: u32 = 4_194_304;
 const FRAME_RATE: u32 = 60;
 const CYCLES_PER_FRAME: u32 = CLOCK_SPEED / FRAME_RATE;
+/// Dirty battery backed RAM is written to disk every 5 seconds
+const BATTERY_SAVE_INTERVAL: u32 = 5 * FRAME_RATE;
 
-fn start_emulation(
+/// State moved into the emulation thread
+struct EmulationThread {
     state: Arc<RwLock<EmulatorState>>,
     terminated: Arc<AtomicBool>,
     paused: Arc<AtomicBool>,
-    frame_sender: Sender<PixelData>,
-) -> JoinHandle<()> {
-    let state = state.clone();
-    let paused_clone = Arc::clone(&paused);
-    let terminated_clone = Arc::clone(&terminated);
-    let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
-
-    thread::spawn(move || {
-        while !terminated_clone.load(Ordering::Relaxed) {
+    /// Frames are sent to the window, `None` in headless mode
+    frame_sender: Option<Sender<PixelData>>,
+    command_receiver: Receiver<EmulatorCommand>,
+    rom_path: Option<PathBuf>,
+    save_path: Option<PathBuf>,
+    serial_output: CaptureBuffer,
+    headless: Option<HeadlessRun>,
+    movie: Option<MovieSession>,
+}
+
+impl EmulationThread {
+    fn spawn(self) -> JoinHandle<Option<Result<HeadlessResult>>> {
+        thread::spawn(move || self.run())
+    }
+
+    fn run(self) -> Option<Result<HeadlessResult>> {
+        let Self {
+            state,
+            terminated,
+            paused,
+            frame_sender,
+            command_receiver,
+            rom_path,
+            save_path,
+            serial_output,
+            mut headless,
+            mut movie,
+        } = self;
+        let frame_duration = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+
+        let mut frames_since_save: u32 = 0;
+        let mut headless_outcome = None;
+        // rewinding is only offered with a window, which holds the rewind key
+        let mut rewind = frame_sender.is_some().then(RewindBuffer::default);
+        let mut frames_since_snapshot: u32 = 0;
+        let mut rewinding = false;
+        let mut rewound = false;
+
+        while !terminated.load(Ordering::Relaxed) {
             let frame_start = Instant::now();
             let mut cycles_this_frame: u32 = 0;
             let mut frame_drawn = false;
+            let mut frame_emulated = false;
+            let mut headless_result = None;
+
+            // save states can also be created and loaded while paused
+            for command in command_receiver.try_iter() {
+                let mut emulator = state.write().unwrap();
 
-            if !paused_clone.load(Ordering::Relaxed) {
+                let result = match command {
+                    EmulatorCommand::Joypad(event) => {
+                        match &mut movie {
+                            Some(movie) => movie.handle_joypad_event(&mut emulator, event),
+                            None => emulator.handle_joypad_event(event),
+                        }
+
+                        Ok(())
+                    }
+                    EmulatorCommand::SaveState(slot) => {
+                        save_state_to_slot(&emulator, rom_path.as_deref(), slot)
+                    }
+                    EmulatorCommand::LoadState(slot) => match &mut movie {
+                        Some(movie) => movie.check_load_state().and_then(|_| {
+                            load_state_from_slot(&mut emulator, rom_path.as_deref(), slot)?;
+                            movie.restart_recording(&emulator)
+                        }),
+                        None => load_state_from_slot(&mut emulator, rom_path.as_deref(), slot),
+                    },
+                    EmulatorCommand::Rewind(true) => match &movie {
+                        Some(movie) => movie.check_load_state().map(|_| rewinding = true),
+                        None => {
+                            rewinding = true;
+                            Ok(())
+                        }
+                    },
+                    // a recording continues from the state rewound to
+                    EmulatorCommand::Rewind(false) => {
+                        rewinding = false;
+
+                        match &mut movie {
+                            Some(movie) if std::mem::take(&mut rewound) => {
+                                movie.restart_recording(&emulator)
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                };
+
+                if let Err(error) = result {
+                    log::error!("{error:?}");
+                }
+            }
+
+            if let (false, true, Some(rewind)) =
+                (paused.load(Ordering::Relaxed), rewinding, &mut rewind)
+            {
                 let mut emulator = state.write().unwrap();
+
+                match rewind.pop(&mut emulator) {
+                    Ok(true) => {
+                        frame_drawn = emulator.framebuffer.is_some();
+                        rewound = true;
+                    }
+                    Ok(false) => {}
+                    Err(error) => {
+                        log::error!("{error:?}");
+                        rewinding = false;
+                    }
+                }
+            } else if !paused.load(Ordering::Relaxed) {
+                let mut emulator = state.write().unwrap();
+                frame_emulated = true;
+
                 while cycles_this_frame < CYCLES_PER_FRAME {
-                    let cycles = emulator.step();
+                    let cycles = match &mut movie {
+                        Some(movie) => movie.step(&mut emulator),
+                        None => emulator.step(),
+                    };
                     cycles_this_frame += cycles as u32;
 
+                    if let Some(headless) = &mut headless {
+                        headless_result = headless.step(&emulator, cycles);
+
+                        if headless_result.is_some() {
+                            break;
+                        }
+                    }
+
                     if emulator.framebuffer.is_some() {
                         frame_drawn = true;
                         break;
                     }
                 }
+
+                if let (true, Some(rewind)) = (frame_drawn, &mut rewind) {
+                    frames_since_snapshot += 1;
+
+                    if frames_since_snapshot >= REWIND_INTERVAL {
+                        frames_since_snapshot = 0;
+
+                        if let Err(error) = rewind.push(&emulator) {
+                            log::error!("{error:?}");
+                        }
+                    }
+                }
+
+                if movie.as_ref().is_some_and(MovieSession::is_finished) {
+                    log::info!("Movie playback finished");
+                    movie = None;
+                }
+
+                for event in emulator.drain_cartridge_events() {
+                    log::debug!("Cartridge event: {:?}", event);
+                }
+
+                let serial_bytes = serial_output.take();
+
+                if !serial_bytes.is_empty() {
+                    log::info!("Serial: {}", String::from_utf8_lossy(&serial_bytes));
+
+                    if let Some(headless) = &mut headless {
+                        headless_result =
+                            headless_result.or_else(|| headless.receive_serial(&serial_bytes));
+                    }
+                }
+            }
+
+            if let (Some(headless), Some(result)) = (&headless, headless_result) {
+                terminated.store(true, Ordering::Relaxed);
+                headless_outcome = Some(headless.finish(result));
+                break;
             }
 
             if frame_drawn {
-                if let Some(framebuffer) = state.read().unwrap().framebuffer {
+                let framebuffer = state.read().unwrap().framebuffer;
+
+                if let (Some(frame_sender), Some(framebuffer)) = (&frame_sender, framebuffer) {
                     frame_sender.send(framebuffer).unwrap();
                 }
             }
 
+            // the battery save only changes while emulating
+            if frame_emulated {
+                frames_since_save += 1;
+            }
+
+            if let Some(save_path) = &save_path {
+                if frames_since_save >= BATTERY_SAVE_INTERVAL {
+                    frames_since_save = 0;
+
+                    let contents = state.write().unwrap().take_dirty_battery_save();
+
+                    if let Some(Err(error)) =
+                        contents.map(|contents| write_battery_save(save_path, &contents))
+                    {
+                        log::error!("{error:?}");
+                    }
+                }
+            }
+
+            if headless.is_some() {
+                continue;
+            }
+
             let elapsed = frame_start.elapsed();
 
             if elapsed < frame_duration {
@@ -135,5 +482,44 @@ fn start_emulation(
                 log::warn!("Frame took too long: {:?}ms", elapsed.as_millis());
             }
         }
-    })
+
+        if let Some(Err(error)) = movie.map(MovieSession::finish) {
+            log::error!("{error:?}");
+        }
+
+        headless_outcome
+    }
+}
+
+fn save_state_to_slot(state: &EmulatorState, rom_path: Option<&Path>, slot: u8) -> Result<()> {
+    let path = rom_path
+        .map(|rom_path| save_state_path(rom_path, slot))
+        .context("Save states are not available without a rom path")?;
+
+    fs::write(&path, state.save_state()?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    log::info!("Saved state to slot {slot}");
+
+    Ok(())
+}
+
+fn load_state_from_slot(
+    state: &mut EmulatorState,
+    rom_path: Option<&Path>,
+    slot: u8,
+) -> Result<()> {
+    let path = rom_path
+        .map(|rom_path| save_state_path(rom_path, slot))
+        .context("Save states are not available without a rom path")?;
+
+    let contents = fs::read(&path).with_context(|| format!("No save state in slot {slot}"))?;
+
+    state
+        .load_state(&contents)
+        .with_context(|| format!("Failed to load {}", path.display()))?;
+
+    log::info!("Loaded state from slot {slot}");
+
+    Ok(())
 }
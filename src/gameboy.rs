@@ -0,0 +1,225 @@
+use crate::cpu::CPU;
+use crate::emulator::EmulatorState;
+use crate::graphics::{PixelData, FRAME_CYCLES};
+use crate::input::{InputPlayer, InputRecorder, InputScript};
+use crate::memory::joypad::Button;
+use crate::memory::serial::SharedSerialDevice;
+use crate::movie::{Movie, MovieStart};
+use anyhow::Result;
+
+/// Snapshot of the CPU registers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+/// Emulator core for embedding into other programs. Emulation only advances when stepped, there
+/// is no window, pacing or thread involved.
+///
+/// Execution is deterministic: the same ROM, start state and input script produce the same
+/// framebuffers and audio samples, as long as the RTC uses the emulated clock and no link cable
+/// is attached.
+pub struct GameBoy {
+    state: EmulatorState,
+    /// Last completed frame
+    framebuffer: PixelData,
+    /// T-cycles emulated by this instance
+    cycles: u64,
+    /// Cycles `run_cycles` ran past its last budget, which count towards the next one
+    overshoot: u64,
+    input: InputPlayer,
+    /// Buttons set with `set_buttons` are recorded into a movie from this start state
+    recording: Option<(InputRecorder, MovieStart)>,
+}
+
+impl GameBoy {
+    /// Starts in the state the boot rom hands off to the cartridge
+    pub fn new(cartridge_contents: &[u8]) -> Result<Self> {
+        Self::init(None, cartridge_contents)
+    }
+
+    /// Starts by executing the boot rom
+    pub fn with_boot_rom(boot_contents: &[u8], cartridge_contents: &[u8]) -> Result<Self> {
+        Self::init(Some(boot_contents), cartridge_contents)
+    }
+
+    fn init(boot_contents: Option<&[u8]>, cartridge_contents: &[u8]) -> Result<Self> {
+        let cpu = CPU::init(boot_contents, cartridge_contents)?;
+
+        Ok(Self {
+            state: EmulatorState::init(cpu),
+            framebuffer: PixelData::default(),
+            cycles: 0,
+            overshoot: 0,
+            input: InputPlayer::default(),
+            recording: None,
+        })
+    }
+
+    /// Executes one instruction, or waits for 4 T-cycles while halted. Returns the T-cycles that
+    /// passed.
+    pub fn step(&mut self) -> u8 {
+        self.overshoot = 0;
+        self.advance()
+    }
+
+    /// Applies due input script events before executing the next instruction
+    fn advance(&mut self) -> u8 {
+        let cycles = self.input.step(&mut self.state);
+
+        if let Some((recorder, _)) = &mut self.recording {
+            recorder.add_cycles(cycles);
+        }
+
+        self.cycles += u64::from(cycles);
+
+        if let Some(framebuffer) = self.state.framebuffer {
+            self.framebuffer = framebuffer;
+        }
+
+        cycles
+    }
+
+    /// Runs for a budget of T-cycles. Instructions are not split, so the cycles run past the
+    /// budget are subtracted from the next call, which keeps consecutive calls in step with
+    /// emulated time.
+    pub fn run_cycles(&mut self, budget: u64) {
+        let end = self.cycles - self.overshoot + budget;
+
+        while self.cycles < end {
+            self.advance();
+        }
+
+        self.overshoot = self.cycles - end;
+    }
+
+    /// Runs until the PPU completes the next frame. While the LCD is off, returns after the time
+    /// of one frame instead.
+    pub fn step_frame(&mut self) {
+        let mut cycles = 0;
+        self.overshoot = 0;
+
+        while cycles < FRAME_CYCLES {
+            cycles += u32::from(self.advance());
+
+            if self.state.framebuffer.is_some() {
+                break;
+            }
+        }
+    }
+
+    /// Shades from 0 (white) to 3 (black) of the last completed frame, row by row
+    pub fn framebuffer(&self) -> &PixelData {
+        &self.framebuffer
+    }
+
+    pub(crate) fn state_mut(&mut self) -> &mut EmulatorState {
+        &mut self.state
+    }
+
+    /// T-cycles emulated since this instance was created
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Replaces the input script, its cycles count from now on
+    pub fn set_input_script(&mut self, script: InputScript) {
+        self.input = InputPlayer::new(script);
+    }
+
+    /// Whether the last step executed `LD B,B`, which test roms use as a software breakpoint
+    pub fn hit_breakpoint(&self) -> bool {
+        self.state.cpu.hit_breakpoint()
+    }
+
+    pub fn registers(&self) -> CpuRegisters {
+        let registers = &self.state.cpu.registers;
+
+        CpuRegisters {
+            a: registers.a,
+            f: u8::from(&registers.f),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            sp: registers.sp,
+            pc: registers.pc,
+        }
+    }
+
+    /// Presses exactly the given buttons and releases all others, until the next event of the
+    /// input script
+    pub fn set_buttons(&mut self, pressed: &[Button]) {
+        self.state.cpu.bus.set_buttons(pressed);
+
+        if let Some((recorder, _)) = &mut self.recording {
+            recorder.record(&self.state);
+        }
+    }
+
+    /// Starts recording the buttons set with `set_buttons` into a movie, which starts at the
+    /// current state
+    pub fn start_recording(&mut self) -> Result<()> {
+        let start = MovieStart::SaveState(self.save_state()?);
+        self.recording = Some((InputRecorder::default(), start));
+
+        Ok(())
+    }
+
+    /// Returns the movie recorded since `start_recording`
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        let (recorder, start) = self.recording.take()?;
+
+        Some(Movie::from_recording(
+            self.state.cpu.bus.rom_checksum(),
+            start,
+            recorder,
+        ))
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.state.set_audio_sample_rate(sample_rate);
+    }
+
+    /// Collects the audio generated since the last call as interleaved stereo samples
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        self.state.drain_audio_samples()
+    }
+
+    /// Attaches a device like `link_cable` or a printer to the serial port
+    pub fn connect_serial(&mut self, device: SharedSerialDevice) {
+        self.state.cpu.bus.connect_serial(device);
+    }
+
+    /// Serializes the whole emulator state, see `load_state`
+    pub fn save_state(&self) -> Result<Vec<u8>> {
+        self.state.save_state()
+    }
+
+    /// Restores a state created by `save_state` with the same ROM
+    pub fn load_state(&mut self, contents: &[u8]) -> Result<()> {
+        self.state.load_state(contents)
+    }
+
+    /// Battery backed cartridge RAM, in the `.sav` format used by other emulators
+    pub fn battery_save(&mut self) -> Option<Vec<u8>> {
+        let bus = &mut self.state.cpu.bus;
+
+        bus.has_battery().then(|| bus.battery_save())
+    }
+
+    pub fn load_battery_save(&mut self, contents: &[u8]) -> Result<()> {
+        self.state.cpu.bus.load_battery_save(contents)
+    }
+}
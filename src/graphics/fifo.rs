@@ -1,5 +1,6 @@
 #![allow(unused)]
-#[derive(Copy, Clone)]
+use serde::{Deserialize, Serialize};
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Fifo {
     queue: [u8; 8],
     index: usize,
@@ -39,6 +40,15 @@ impl Fifo {
         Ok(value)
     }
 
+    /// Returns the pixel at the given offset from the front of the queue
+    pub fn get_mut(&mut self, offset: usize) -> Option<&mut u8> {
+        if offset >= self.length {
+            return None;
+        }
+
+        Some(&mut self.queue[(self.index + offset) % self.queue.len()])
+    }
+
     pub fn is_empty(&self) -> bool {
         self.length == 0
     }
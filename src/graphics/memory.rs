@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 use crate::memory::bus::{get_bit_status, set_bit_status, Bus};
 
+use super::object::DmgPalette;
 use super::PPUMode;
 
 const LCDC_BIT_LCD_ENABLE: u8 = 7;
@@ -28,6 +29,20 @@ impl Bus {
         self.get_lcdc_bit(LCDC_BIT_LCD_ENABLE)
     }
 
+    /// LCDC: Turning the LCD off resets LY and the PPU mode immediately, which unlocks VRAM and
+    /// OAM. The PPU picks up the change on its next step.
+    pub(crate) fn write_lcd_control(&mut self, value: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.set_lcd_control(value);
+
+        if was_enabled && !self.lcd_enabled() {
+            self.set_lcd_y(0);
+            self.update_ppu_mode(PPUMode::HorizontalBlank);
+            self.lcd_status_write_mode(PPUMode::HorizontalBlank);
+            self.set_stat_line(false);
+        }
+    }
+
     /// LCDC.5: Returns whether window is enabled
     pub(crate) fn window_enabled(&self) -> bool {
         self.get_lcdc_bit(LCDC_BIT_WINDOW_ENABLE)
@@ -144,6 +159,9 @@ impl Bus {
 
 const LCD_STAT_BIT_LYC: u8 = 2;
 const LCD_STAT_BIT_MODE: u8 = 0;
+/// The interrupt enables are writable, the LYC flag and the PPU mode are read-only
+const LCD_STAT_WRITABLE_MASK: u8 = 0b01111000;
+const LCD_STAT_MODE_MASK: u8 = 0b11;
 
 pub(super) struct StatCondition {
     pub(super) lyc: bool,
@@ -170,6 +188,11 @@ impl Bus {
     }
 
     pub(super) fn lcd_status_set_mode(&mut self, mode: PPUMode) {
+        self.lcd_status_write_mode(mode);
+        self.update_stat_line();
+    }
+
+    fn lcd_status_write_mode(&mut self, mode: PPUMode) {
         let value = match mode {
             PPUMode::OBJSearch => 0b10,
             PPUMode::SendPixels => 0b11,
@@ -178,7 +201,54 @@ impl Bus {
         };
 
         let current = self.get_lcd_stat();
-        self.set_lcd_stat(current & (0xFC | value));
+        self.set_lcd_stat((current & !LCD_STAT_MODE_MASK) | value);
+    }
+
+    /// STAT: Only the interrupt enables can be written, which may raise the STAT line
+    pub(crate) fn write_lcd_stat(&mut self, value: u8) {
+        let current = self.get_lcd_stat();
+        self.set_lcd_stat((current & !LCD_STAT_WRITABLE_MASK) | (value & LCD_STAT_WRITABLE_MASK));
+        self.update_stat_line();
+    }
+
+    /// Whether any enabled STAT interrupt source is active
+    fn stat_sources_active(&self) -> bool {
+        let conditions = self.lcd_status_condition();
+        let stat = self.get_lcd_stat();
+
+        let mode_source = match stat & LCD_STAT_MODE_MASK {
+            0b00 => conditions.mode0,
+            0b01 => conditions.mode1,
+            0b10 => conditions.mode2,
+            _ => false,
+        };
+
+        mode_source || (conditions.lyc && get_bit_status(stat, LCD_STAT_BIT_LYC))
+    }
+
+    /// Requests the STAT interrupt only on a rising edge of the STAT line. A source becoming
+    /// active while another one keeps the line high does not trigger another interrupt
+    /// ("STAT blocking").
+    fn set_stat_line_status(&mut self, status: bool) {
+        if status && !self.get_stat_line() {
+            self.request_stat_interrupt();
+        }
+
+        self.set_stat_line(status);
+    }
+
+    /// The STAT line stays low while the LCD is off
+    pub(super) fn update_stat_line(&mut self) {
+        let status = self.lcd_enabled() && self.stat_sources_active();
+        self.set_stat_line_status(status);
+    }
+
+    /// On entering VBlank at line 144 the mode 2 source is active for a moment as well
+    pub(super) fn trigger_vblank_oam_stat(&mut self) {
+        self.lcd_status_write_mode(PPUMode::VerticalBlank);
+
+        let status = self.stat_sources_active() || self.lcd_status_condition().mode2;
+        self.set_stat_line_status(status);
     }
 
     pub(crate) fn update_line(&mut self) {
@@ -196,9 +266,28 @@ impl Bus {
 
     pub fn update_stat_lyc(&mut self) {
         self.lcd_status_set_lyc(self.lcd_y_compare());
+        self.update_stat_line();
+    }
+}
 
-        if self.lcd_status_condition().lyc && self.lcd_y_compare() {
-            self.request_stat_interrupt();
-        }
+/// Maps a 2-bit color ID to its shade, from 0 (white) to 3 (black)
+fn apply_palette(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+impl Bus {
+    /// BGP: Shade of a background or window color ID
+    pub(super) fn background_shade(&self, color: u8) -> u8 {
+        apply_palette(self.get_bg_palette(), color)
+    }
+
+    /// OBP0/OBP1: Shade of an object color ID, color 0 is transparent and never reaches this
+    pub(super) fn object_shade(&self, palette: DmgPalette, color: u8) -> u8 {
+        let palette = match palette {
+            DmgPalette::OBP0 => self.get_obj_palette_0(),
+            DmgPalette::OBP1 => self.get_obj_palette_1(),
+        };
+
+        apply_palette(palette, color)
     }
 }
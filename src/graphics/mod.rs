@@ -3,7 +3,11 @@ mod memory;
 mod object;
 mod pixel_fetcher;
 mod ppu;
+mod screenshot;
+#[cfg(feature = "window")]
 mod window;
 
 pub use ppu::*;
+pub use screenshot::{write_png, GRAYSCALE_SHADES};
+#[cfg(feature = "window")]
 pub use window::App;
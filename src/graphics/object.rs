@@ -1,9 +1,9 @@
 use crate::memory::bus::Bus;
+use serde::{Deserialize, Serialize};
 
 use super::memory::OBJECT_SIZE;
 
-#[allow(unused)]
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
 pub(super) struct ObjectAttribute {
     pub(super) y_position: u8,
     pub(super) x_position: u8,
@@ -11,49 +11,43 @@ pub(super) struct ObjectAttribute {
     pub(super) attributes: u8,
 }
 
-pub(super) enum ObjectPriority {
-    LOW,
-    HIGH,
-}
+/// Attribute bits that are carried along with object pixels in the sprite FIFO
+pub(super) const OBJECT_ATTRIBUTE_PRIORITY: u8 = 0b10000000;
+pub(super) const OBJECT_ATTRIBUTE_PALETTE: u8 = 0b00010000;
+const OBJECT_ATTRIBUTE_Y_FLIP: u8 = 0b01000000;
+const OBJECT_ATTRIBUTE_X_FLIP: u8 = 0b00100000;
 
 pub(super) enum DmgPalette {
     OBP0,
     OBP1,
 }
 
-#[allow(unused)]
-impl ObjectAttribute {
-    pub(super) fn get_priority(&self) -> ObjectPriority {
-        if self.attributes & 0b10000000 == 0 {
-            ObjectPriority::HIGH
+impl DmgPalette {
+    /// Palette selected by the attributes of an object or by those carried by a sprite pixel
+    pub(super) fn from_attributes(attributes: u8) -> Self {
+        if attributes & OBJECT_ATTRIBUTE_PALETTE == 0 {
+            DmgPalette::OBP0
         } else {
-            ObjectPriority::LOW
+            DmgPalette::OBP1
         }
     }
+}
 
+impl ObjectAttribute {
     pub(super) fn is_y_flipped(&self) -> bool {
-        self.attributes & 0b01000000 != 0
+        self.attributes & OBJECT_ATTRIBUTE_Y_FLIP != 0
     }
 
     pub(super) fn is_x_flipped(&self) -> bool {
-        self.attributes & 0b00100000 != 0
-    }
-
-    pub(super) fn get_dmg_palette(&self) -> DmgPalette {
-        if self.attributes & 0b00010000 == 0 {
-            DmgPalette::OBP0
-        } else {
-            DmgPalette::OBP1
-        }
+        self.attributes & OBJECT_ATTRIBUTE_X_FLIP != 0
     }
 }
 
-#[derive(Clone, Copy)]
+/// Objects selected during OAM scan, in OAM order. Fetched objects are taken out of the buffer.
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub(super) struct ObjectBuffer {
     pub(super) buffer: [Option<ObjectAttribute>; 10],
     length: usize,
-    head: usize,
-    tail: usize,
     oam_index: u8,
     t_cycles_elapsed: u16,
 }
@@ -63,8 +57,6 @@ impl ObjectBuffer {
         Self {
             buffer: [None; 10],
             length: 0,
-            head: 0,
-            tail: 0,
             oam_index: 0,
             t_cycles_elapsed: 0,
         }
@@ -187,9 +179,10 @@ impl ObjectBuffer {
                 attributes: bus.oam.read(address + 3),
             };
 
-            if current_line + OBJECT_SIZE < object_y
-                || current_line + OBJECT_SIZE >= object_y + object_size
-            {
+            let line = u16::from(current_line) + u16::from(OBJECT_SIZE);
+            let object_y = u16::from(object_y);
+
+            if line < object_y || line >= object_y + u16::from(object_size) {
                 self.oam_index += 1;
                 continue;
             }
@@ -205,30 +198,27 @@ impl ObjectBuffer {
             return Err(());
         }
 
-        self.buffer[self.tail] = Some(obj);
-        self.tail = (self.tail + 1) % self.buffer.len();
+        self.buffer[self.length] = Some(obj);
         self.length += 1;
         Ok(())
     }
 
-    pub fn pop_front_if<F>(&mut self, mut condition: F) -> Option<ObjectAttribute>
-    where
-        F: FnMut(&ObjectAttribute) -> bool,
-    {
-        if self.length == 0 {
-            return None;
-        }
-
-        let head_obj = self.buffer[self.head].as_ref()?;
-
-        if condition(head_obj) {
-            let val = self.buffer[self.head].take();
-            self.head = (self.head + 1) % self.buffer.len();
-            self.length -= 1;
-            val
-        } else {
-            None
-        }
+    /// Takes the next object covering the pixel at `render_x` out of the buffer. On DMG the
+    /// object with the smaller X coordinate wins, ties are resolved by OAM order.
+    pub(super) fn take_object_at(&mut self, render_x: u8) -> Option<ObjectAttribute> {
+        let render_x = u16::from(render_x);
+
+        let (index, _) = self.buffer[..self.length]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| Some((index, (*object)?)))
+            .filter(|(_, object)| {
+                let x_position = u16::from(object.x_position);
+                x_position <= render_x + 8 && x_position > render_x
+            })
+            .min_by_key(|(_, object)| object.x_position)?;
+
+        self.buffer[index].take()
     }
 
     fn is_full(&self) -> bool {
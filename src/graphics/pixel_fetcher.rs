@@ -1,9 +1,14 @@
 #![allow(unused)]
 use crate::memory::bus::Bus;
+use serde::{Deserialize, Serialize};
 
 use super::{
     fifo::Fifo,
-    object::{ObjectAttribute, ObjectBuffer},
+    memory::OBJECT_SIZE,
+    object::{
+        DmgPalette, ObjectAttribute, ObjectBuffer, OBJECT_ATTRIBUTE_PALETTE,
+        OBJECT_ATTRIBUTE_PRIORITY,
+    },
     PixelData, LCD_WIDTH,
 };
 
@@ -12,8 +17,11 @@ const SCROLL_Y: u16 = 0xFF42;
 const WINDOW_X: u16 = 0xFF4B;
 const WINDOW_Y: u16 = 0xFF4A;
 const TILE_SIZE: u8 = 8;
+/// Dots the background fetcher and the pixel output are stalled for while fetching an object
+const OBJECT_FETCH_CYCLES: u8 = 6;
+const PIXEL_COLOR_MASK: u8 = 0b11;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 enum PixelFetcherStep {
     GetTile,
     GetTileDataLow,
@@ -22,19 +30,20 @@ enum PixelFetcherStep {
     Push,
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 enum FetchMode {
     #[default]
     Background,
     Window,
-    Object(ObjectAttribute),
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub(crate) struct PixelFetcher {
     current_step: PixelFetcherStep,
-    paused_step: Option<PixelFetcherStep>,
-    sprite_delay: u8,
+    /// Remaining dots of the current background fetcher step
+    step_cycles: u8,
+    /// Object currently being fetched and the remaining dots of the fetch
+    object_fetch: Option<(ObjectAttribute, u8)>,
     current_line: u8,
     /// Tile number fetching
     fetch_mode: FetchMode,
@@ -57,8 +66,8 @@ impl PixelFetcher {
     pub fn init() -> Self {
         Self {
             current_step: PixelFetcherStep::Sleep(6),
-            paused_step: None,
-            sprite_delay: 0,
+            step_cycles: 0,
+            object_fetch: None,
             current_line: 0,
             fetch_mode: FetchMode::default(),
             fetcher_x: 0,
@@ -97,46 +106,74 @@ impl PixelFetcher {
 
     // TODO: avoid taking and writing the PixelData directly and just return individual pixels
     // instead
-    // TODO: return any penalties that extend the number of cycles mode 3 takes
+    /// Mode 3 lasts until the last pixel of the line has been pushed, which is delayed by the
+    /// SCX % 8 discards, window activation and object fetches. Once the line is finished, the
+    /// dots left over from `passed_t_cycles` are returned.
     pub fn step(
         &mut self,
         bus: &Bus,
         object_buffer: &mut ObjectBuffer,
         passed_t_cycles: u8,
         current_frame: &mut PixelData,
-    ) {
-        let mut t_counter = 0;
-
-        // TODO: check where exactly to start pixel fetching for objects
-        while t_counter < passed_t_cycles {
-            let (next_step, spent_cycles) = match self.fetch_mode {
-                FetchMode::Object(_) => self.step_subtask_object(bus),
-                FetchMode::Background | FetchMode::Window => self.step_subtask(bus),
-            };
-
-            self.current_step = next_step;
-
-            for c in (0..spent_cycles) {
-                if let Some(object) = self.get_object_for_current_position(object_buffer) {
-                    self.set_object_fetch_mode(object);
-                    self.sprite_delay = spent_cycles - c;
-                    break;
-                } else {
-                    self.fetch_mode = match self.fetch_mode {
-                        FetchMode::Object(_) => FetchMode::Background,
-                        _ => self.fetch_mode,
-                    };
-                }
-
-                t_counter += 1;
+    ) -> Option<u8> {
+        for dot in 0..passed_t_cycles {
+            if self.is_line_finished() {
+                return Some(passed_t_cycles - dot);
+            }
 
-                let pixel_pushed = self.try_push_pixel_to_screen(bus, current_frame);
+            if self.step_object_fetch(bus) {
+                continue;
+            }
 
-                if pixel_pushed && self.window_reached(bus) {
-                    self.set_window_fetch_mode();
+            if bus.objects_enabled() && usize::from(self.render_x) < LCD_WIDTH {
+                if let Some(object) = object_buffer.take_object_at(self.render_x) {
+                    self.object_fetch = Some((object, OBJECT_FETCH_CYCLES));
+                    continue;
                 }
             }
+
+            if self.window_reached(bus) {
+                self.set_window_fetch_mode();
+            }
+
+            self.step_background_fetch(bus);
+            self.try_push_pixel_to_screen(bus, current_frame);
+        }
+
+        self.is_line_finished().then_some(0)
+    }
+
+    fn is_line_finished(&self) -> bool {
+        usize::from(self.render_x) >= LCD_WIDTH
+    }
+
+    /// Advances a pending object fetch by one dot. Returns whether the background fetcher and the
+    /// pixel output are stalled by the fetch.
+    fn step_object_fetch(&mut self, bus: &Bus) -> bool {
+        let Some((object, remaining_cycles)) = self.object_fetch else {
+            return false;
+        };
+
+        if remaining_cycles > 1 {
+            self.object_fetch = Some((object, remaining_cycles - 1));
+        } else {
+            self.object_fetch = None;
+            self.push_object_to_queue(bus, object);
+        }
+
+        true
+    }
+
+    fn step_background_fetch(&mut self, bus: &Bus) {
+        if self.step_cycles > 0 {
+            self.step_cycles -= 1;
+            return;
         }
+
+        let (next_step, spent_cycles) = self.step_subtask(bus);
+
+        self.current_step = next_step;
+        self.step_cycles = spent_cycles - 1;
     }
 
     fn step_subtask(&mut self, bus: &Bus) -> (PixelFetcherStep, u8) {
@@ -161,12 +198,7 @@ impl PixelFetcher {
                 (PixelFetcherStep::Push, 2)
             }
             PixelFetcherStep::Push => {
-                let push_is_allowed = match self.fetch_mode {
-                    FetchMode::Object(_) => true,
-                    FetchMode::Background | FetchMode::Window => self.background_queue.is_empty(),
-                };
-
-                if push_is_allowed {
+                if self.background_queue.is_empty() {
                     self.push_pixel_data_to_queue();
                     (PixelFetcherStep::GetTile, 2)
                 } else {
@@ -176,43 +208,11 @@ impl PixelFetcher {
         }
     }
 
-    // TODO: merge with other step_subtask method if possible
-    fn step_subtask_object(&mut self, bus: &Bus) -> (PixelFetcherStep, u8) {
-        match self.current_step {
-            PixelFetcherStep::Sleep(counter) => {
-                if counter == 1 {
-                    (PixelFetcherStep::GetTile, 1)
-                } else {
-                    (PixelFetcherStep::Sleep(counter - 1), 1)
-                }
-            }
-            PixelFetcherStep::GetTile => {
-                self.tile_number = self.fetch_tile_number(bus);
-                (PixelFetcherStep::GetTileDataLow, 2)
-            }
-            PixelFetcherStep::GetTileDataLow => {
-                self.tile_data_low = self.fetch_tile_data_low(bus);
-                (PixelFetcherStep::GetTileDataHigh, 1)
-            }
-            PixelFetcherStep::GetTileDataHigh => {
-                self.tile_data_high = self.fetch_tile_data_high(bus);
-                (PixelFetcherStep::Push, 1)
-            }
-            PixelFetcherStep::Push => {
-                self.push_pixel_data_to_queue();
-                let sprite_delay = self.sprite_delay;
-                self.sprite_delay = 0;
-                (self.paused_step.unwrap(), 2 + sprite_delay)
-            }
-        }
-    }
-
     /// Get the tile number used as an index for accessing the tile data from vram in the next two
     /// steps:
     ///   * Background & Window: Access the current 32x32 tile map and return the tile index based
     ///     on the current position. The current tile map is detected internally using the LCDC
     ///     register.
-    ///   * Object: Read tile number from current object
     fn fetch_tile_number(&self, bus: &Bus) -> u8 {
         match self.fetch_mode {
             FetchMode::Background => {
@@ -234,23 +234,19 @@ impl PixelFetcher {
 
                 bus.ppu_read(tilemap_address)
             }
-            FetchMode::Object(object_attribute) => object_attribute.tile_index,
         }
     }
 
     /// Returns the lower byte of the tile at the address pointed to by the tile map at the given
     /// index.
     fn fetch_tile_data_low(&mut self, bus: &Bus) -> u8 {
-        let address = (match self.fetch_mode {
-            FetchMode::Background | FetchMode::Window => bus.get_bg_window_tile_data_area(),
-            FetchMode::Object(_) => bus.get_object_tile_data_area(),
-        })
-        .get_tile_address(self.tile_number);
+        let address = bus
+            .get_bg_window_tile_data_area()
+            .get_tile_address(self.tile_number);
 
         let offset = match self.fetch_mode {
             FetchMode::Background => self.current_line.wrapping_add(bus.get_scroll_y()),
             FetchMode::Window => self.window_line_counter,
-            FetchMode::Object(_) => 0,
         } % TILE_SIZE;
 
         self.tile_data_address = address + (offset * 2) as u16;
@@ -275,15 +271,58 @@ impl PixelFetcher {
 
             let pixel = (high << 1) | low;
 
-            match self.fetch_mode {
-                FetchMode::Background | FetchMode::Window => self.background_queue.push(pixel),
-                FetchMode::Object(_) => self.sprite_queue.push(pixel),
-            };
+            self.background_queue.push(pixel);
         }
 
         self.fetcher_x += 1;
     }
 
+    /// Fetches the current row of the object and merges it into the sprite fifo queue
+    /// (`self.sprite_queue`), which is aligned to the next pixel pushed to the screen. Pixels of
+    /// objects fetched earlier have priority, only transparent pixels are replaced.
+    fn push_object_to_queue(&mut self, bus: &Bus, object: ObjectAttribute) {
+        let object_size = bus.get_obj_size();
+
+        let mut row =
+            (self.current_line + OBJECT_SIZE).wrapping_sub(object.y_position) % object_size;
+
+        if object.is_y_flipped() {
+            row = object_size - 1 - row;
+        }
+
+        // 8x16 objects ignore bit 0 of the tile index, the bottom half is stored in the next tile
+        let tile_index = if object_size == 16 {
+            object.tile_index & 0xFE
+        } else {
+            object.tile_index
+        };
+
+        let address =
+            bus.get_object_tile_data_area().get_tile_address(tile_index) + u16::from(row) * 2;
+        let tile_data_low = bus.ppu_read(address);
+        let tile_data_high = bus.ppu_read(address + 1);
+
+        // objects partially left of the screen start with some of their pixels already passed
+        let skipped_pixels = (self.render_x + TILE_SIZE).saturating_sub(object.x_position);
+        let attributes = object.attributes & (OBJECT_ATTRIBUTE_PRIORITY | OBJECT_ATTRIBUTE_PALETTE);
+
+        for i in skipped_pixels..TILE_SIZE {
+            let offset = if object.is_x_flipped() { i } else { 7 - i };
+            let high = (tile_data_high >> offset) & 1;
+            let low = (tile_data_low >> offset) & 1;
+
+            let pixel = (high << 1) | low | attributes;
+
+            match self.sprite_queue.get_mut(usize::from(i - skipped_pixels)) {
+                Some(queued) if *queued & PIXEL_COLOR_MASK == 0 => *queued = pixel,
+                Some(_) => {}
+                None => {
+                    self.sprite_queue.push(pixel);
+                }
+            }
+        }
+    }
+
     /// Tries to push pixels from the queues to the current frame.
     fn try_push_pixel_to_screen(&mut self, bus: &Bus, frame: &mut PixelData) -> bool {
         if self.discard_counter > 0 && self.background_queue.pop().is_ok() {
@@ -301,20 +340,22 @@ impl PixelFetcher {
 
         let bg_pixel = if bus.bg_window_enabled() { bg_pixel } else { 0 };
 
-        // let final_pixel = match self.sprite_queue.pop() {
-        //     Ok(sprite_pixel) => {
-        //         if !bus.objects_enabled() || (sprite_pixel == 0 && bg_pixel != 0) {
-        //             bg_pixel
-        //         } else {
-        //             sprite_pixel
-        //         }
-        //     }
-        //     Err(_) => bg_pixel,
-        // };
-        let final_pixel = bg_pixel;
+        let shade = match self.sprite_queue.pop() {
+            Ok(sprite_pixel)
+                if bus.objects_enabled() && sprite_is_visible(bg_pixel, sprite_pixel) =>
+            {
+                bus.object_shade(
+                    DmgPalette::from_attributes(sprite_pixel),
+                    sprite_pixel & PIXEL_COLOR_MASK,
+                )
+            }
+            // With LCDC.0 cleared the background and window are blank (white) on DMG
+            _ if !bus.bg_window_enabled() => 0,
+            _ => bus.background_shade(bg_pixel),
+        };
 
         let index = ((self.current_line as usize) * LCD_WIDTH + (self.render_x as usize));
-        frame.0[index] = final_pixel;
+        frame.0[index] = shade;
 
         self.render_x += 1;
 
@@ -345,25 +386,18 @@ impl PixelFetcher {
         self.fetch_mode = FetchMode::Window;
         self.fetcher_x = 0;
         self.current_step = PixelFetcherStep::GetTile;
+        self.step_cycles = 0;
         self.background_queue.clear();
+        // the fine scroll of the background does not apply to the window
+        self.discard_counter = 0;
     }
+}
 
-    fn get_object_for_current_position(
-        &self,
-        object_buffer: &mut ObjectBuffer,
-    ) -> Option<ObjectAttribute> {
-        object_buffer.pop_front_if(|object| {
-            object.x_position <= self.render_x + 8 && object.x_position > self.render_x
-        })
-    }
+/// Transparent object pixels and object pixels behind non-zero background colors (OBJ-to-BG
+/// priority) show the background pixel instead
+fn sprite_is_visible(bg_pixel: u8, sprite_pixel: u8) -> bool {
+    let sprite_color = sprite_pixel & PIXEL_COLOR_MASK;
+    let behind_bg = sprite_pixel & OBJECT_ATTRIBUTE_PRIORITY != 0 && bg_pixel != 0;
 
-    fn set_object_fetch_mode(&mut self, object_attribute: ObjectAttribute) {
-        if matches!(self.fetch_mode, FetchMode::Object(_)) {
-            return;
-        }
-
-        self.fetch_mode = FetchMode::Object(object_attribute);
-        self.paused_step = Some(self.current_step);
-        self.current_step = PixelFetcherStep::GetTile;
-    }
+    sprite_color != 0 && !behind_bg
 }
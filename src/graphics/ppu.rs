@@ -1,13 +1,19 @@
 use crate::memory::bus::Bus;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
 
 use super::{object::ObjectBuffer, pixel_fetcher::PixelFetcher};
 
 pub const LCD_WIDTH: usize = 160;
 pub const LCD_HEIGHT: usize = 144;
 const CYCLES_PER_LINE: u16 = 456;
+/// T-cycles of one frame of 154 lines, including VBlank
+pub const FRAME_CYCLES: u32 = 154 * CYCLES_PER_LINE as u32;
+const OBJ_SEARCH_CYCLES: u16 = 80;
 
-#[derive(Clone, Copy)]
-pub struct PixelData(pub [u8; LCD_WIDTH * LCD_HEIGHT]);
+/// Shades from 0 (white) to 3 (black), after applying the BGP, OBP0 and OBP1 palettes
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct PixelData(#[serde(with = "BigArray")] pub [u8; LCD_WIDTH * LCD_HEIGHT]);
 
 impl Default for PixelData {
     fn default() -> Self {
@@ -15,7 +21,7 @@ impl Default for PixelData {
     }
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
 pub enum PPUMode {
     #[default]
     OBJSearch,
@@ -24,7 +30,7 @@ pub enum PPUMode {
     VerticalBlank,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct PPU {
     mode: PPUMode,
     mode_timer: u16,
@@ -32,7 +38,12 @@ pub struct PPU {
     pixel_fetcher: PixelFetcher,
     object_buffer: ObjectBuffer,
     screen_finished: bool,
-    scanline_x_scroll: u8,
+    /// Whether the LCD was on during the last step, to detect LCDC.7 toggles
+    lcd_enabled: bool,
+    /// The first frame after turning the LCD on is not displayed
+    skip_frame: bool,
+    /// Length of Mode 3 on the current line, HBlank is shortened by the same amount
+    send_pixels_cycles: u16,
 }
 
 impl PPU {
@@ -44,39 +55,37 @@ impl PPU {
             pixel_fetcher: PixelFetcher::init(),
             object_buffer: ObjectBuffer::init(),
             screen_finished: false,
-            scanline_x_scroll: 0,
+            lcd_enabled: true,
+            skip_frame: false,
+            send_pixels_cycles: 0,
         }
     }
 
-    fn check_mode_change(self) -> (bool, u16) {
-        // let send_pixel_timer: u16 = (172 + self.scanline_x_scroll % 8).into();
-        let send_pixel_timer = 289;
-        // let hblank_timer: u16 = (204 - self.scanline_x_scroll % 8).into();
-        let hblank_timer = 87;
-        let timer = self.mode_timer;
-
-        match self.mode {
-            PPUMode::OBJSearch if timer >= 80 => (true, timer - 80),
-            // TODO: account for this modes penalties, see: https://gbdev.io/pandocs/Rendering.html#mode-3-length
-            PPUMode::SendPixels if timer >= send_pixel_timer => (true, timer - send_pixel_timer),
-            PPUMode::HorizontalBlank if timer >= hblank_timer => (true, timer - hblank_timer),
-            PPUMode::VerticalBlank if timer >= CYCLES_PER_LINE => (true, timer - CYCLES_PER_LINE),
-            _ => (false, 0),
-        }
+    /// Dots left in the current mode, Mode 3 has no fixed length and ends when the pixel fetcher
+    /// has finished the line. HBlank takes up the rest of the 456 dots of the line.
+    fn remaining_mode_cycles(&self) -> u16 {
+        let mode_length = match self.mode {
+            PPUMode::OBJSearch => OBJ_SEARCH_CYCLES,
+            PPUMode::SendPixels => u16::MAX,
+            PPUMode::HorizontalBlank => {
+                (CYCLES_PER_LINE - OBJ_SEARCH_CYCLES).saturating_sub(self.send_pixels_cycles)
+            }
+            PPUMode::VerticalBlank => CYCLES_PER_LINE,
+        };
+
+        mode_length.saturating_sub(self.mode_timer)
     }
 
     fn change_mode(&mut self, bus: &mut Bus) {
         self.mode = match self.mode {
-            PPUMode::OBJSearch => {
-                self.scanline_x_scroll = bus.get_scroll_x();
-                PPUMode::SendPixels
-            }
+            PPUMode::OBJSearch => PPUMode::SendPixels,
             PPUMode::SendPixels => PPUMode::HorizontalBlank,
             PPUMode::HorizontalBlank => {
                 bus.update_line();
 
                 if bus.current_line() == 144 {
                     bus.request_vblank_interrupt();
+                    bus.trigger_vblank_oam_stat();
                     PPUMode::VerticalBlank
                 } else {
                     self.object_buffer.reset_line();
@@ -97,66 +106,99 @@ impl PPU {
                 }
             }
         };
-        self.update_stat_mode(bus);
+        bus.update_ppu_mode(self.mode);
+        bus.lcd_status_set_mode(self.mode);
     }
 
-    fn update_stat_mode(&self, bus: &mut Bus) {
-        bus.lcd_status_set_mode(self.mode);
+    /// Runs the current mode for up to `t_cycles` dots and returns the dots left over after a
+    /// mode change
+    fn step_mode(&mut self, t_cycles: u8, bus: &mut Bus) -> u8 {
+        let mode_cycles = match self.mode {
+            PPUMode::SendPixels => {
+                let leftover_cycles = self.pixel_fetcher.step(
+                    bus,
+                    &mut self.object_buffer,
+                    t_cycles,
+                    &mut self.current_frame,
+                );
+
+                self.mode_timer += u16::from(t_cycles - leftover_cycles.unwrap_or(0));
+
+                if let Some(leftover_cycles) = leftover_cycles {
+                    self.send_pixels_cycles = self.mode_timer;
+                    self.mode_timer = 0;
+                    self.change_mode(bus);
+                    return leftover_cycles;
+                }
 
-        let stat_conditions = bus.lcd_status_condition();
-        let trigger_interrupt = match self.mode {
-            PPUMode::OBJSearch if stat_conditions.mode2 => true,
-            PPUMode::VerticalBlank if stat_conditions.mode1 => true,
-            PPUMode::HorizontalBlank if stat_conditions.mode0 => true,
-            _ => false,
+                return 0;
+            }
+            _ => u16::from(t_cycles).min(self.remaining_mode_cycles()) as u8,
         };
 
-        if trigger_interrupt {
-            bus.request_stat_interrupt();
+        if matches!(self.mode, PPUMode::OBJSearch) {
+            self.object_buffer.step(bus, mode_cycles);
+        }
+
+        self.mode_timer += u16::from(mode_cycles);
+
+        if self.remaining_mode_cycles() > 0 {
+            return 0;
         }
+
+        self.mode_timer = 0;
+        self.change_mode(bus);
+
+        t_cycles - mode_cycles
     }
 
-    pub(crate) fn step(&mut self, t_cycles: u8, bus: &mut Bus) -> Option<PixelData> {
-        if !bus.lcd_enabled() {
+    /// The bus already reset LY and the STAT mode when LCDC.7 was cleared. Outputs a blank frame
+    /// once, the screen stays blank while the LCD is off.
+    fn disable_lcd(&mut self) -> Option<PixelData> {
+        if !self.lcd_enabled {
             return None;
         }
 
-        self.mode_timer = self.mode_timer.saturating_add(t_cycles.into());
+        self.lcd_enabled = false;
+        self.mode = PPUMode::HorizontalBlank;
+        self.mode_timer = 0;
+        self.current_frame = PixelData::default();
+        self.screen_finished = false;
 
-        let (should_change_mode, remaining_cycles) = self.check_mode_change();
+        Some(PixelData::default())
+    }
 
-        if matches!(self.mode, PPUMode::SendPixels) && remaining_cycles != 0 {
-            self.pixel_fetcher.step(
-                bus,
-                &mut self.object_buffer,
-                remaining_cycles as u8,
-                &mut self.current_frame,
-            );
+    /// Restarts the frame at line 0. The first line skips the mode 2 STAT interrupt and reports
+    /// mode 0 until mode 3 starts.
+    fn enable_lcd(&mut self, bus: &mut Bus) {
+        self.lcd_enabled = true;
+        self.skip_frame = true;
+        self.mode = PPUMode::OBJSearch;
+        self.mode_timer = 0;
+        self.object_buffer.reset_line();
+        self.pixel_fetcher.reset_frame(bus);
+
+        bus.update_stat_lyc();
+    }
+
+    pub(crate) fn step(&mut self, t_cycles: u8, bus: &mut Bus) -> Option<PixelData> {
+        if !bus.lcd_enabled() {
+            return self.disable_lcd();
         }
 
-        if should_change_mode {
-            self.mode_timer = remaining_cycles;
-            self.change_mode(bus);
-            bus.update_ppu_mode(self.mode);
+        if !self.lcd_enabled {
+            self.enable_lcd(bus);
         }
 
-        match self.mode {
-            PPUMode::OBJSearch => {
-                self.object_buffer.step(bus, t_cycles);
-                // if bus.current_line() == 0 {
-                //     dbg!(self.object_buffer.buffer);
-                //     println!();
-                // }
-            }
-            PPUMode::SendPixels => {
-                self.pixel_fetcher.step(
-                    bus,
-                    &mut self.object_buffer,
-                    t_cycles,
-                    &mut self.current_frame,
-                );
-            }
-            _ => {}
+        let mut t_cycles = t_cycles;
+
+        while t_cycles > 0 {
+            t_cycles = self.step_mode(t_cycles, bus);
+        }
+
+        if self.screen_finished && self.skip_frame {
+            self.screen_finished = false;
+            self.skip_frame = false;
         }
 
         if self.screen_finished {
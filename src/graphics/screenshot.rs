@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use super::{PixelData, LCD_HEIGHT, LCD_WIDTH};
+
+/// RGB colors of the shades 0 (white) to 3 (black), matching the reference images of test roms
+pub const GRAYSCALE_SHADES: [[u8; 3]; 4] = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+
+impl PixelData {
+    /// Converts the shades to grayscale RGB pixels
+    pub fn to_rgb(self) -> Vec<u8> {
+        self.0
+            .iter()
+            .flat_map(|&shade| GRAYSCALE_SHADES[usize::from(shade & 0b11)])
+            .collect()
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        write_png(path, LCD_WIDTH as u32, LCD_HEIGHT as u32, &self.to_rgb())
+    }
+}
+
+/// Writes 8-bit RGB pixels to a PNG file
+pub fn write_png(path: &Path, width: u32, height: u32, rgb: &[u8]) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgb)?;
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 #![deny(clippy::all)]
 #![allow(unused)]
 
-use crossbeam_channel::Receiver;
+use crossbeam_channel::{Receiver, Sender};
 use error_iter::ErrorIter as _;
 use log::{error, info};
 use pixels::{Error, Pixels, SurfaceTexture};
@@ -11,12 +11,14 @@ use std::sync::{
 };
 use winit::application::ApplicationHandler;
 use winit::dpi::LogicalSize;
-use winit::event::{Event, KeyEvent, WindowEvent};
+use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::{Key, KeyCode, NamedKey};
+use winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
 use winit::window::{Window, WindowId};
 
 use super::{PixelData, LCD_HEIGHT, LCD_WIDTH};
+use crate::emulator::EmulatorCommand;
+use crate::memory::joypad::{Button, JoypadEvent};
 
 const BOX_SIZE: i16 = 32;
 
@@ -24,6 +26,9 @@ const BOX_SIZE: i16 = 32;
 pub struct App {
     // TODO: may receive events other than Frames
     frame_receiver: Receiver<PixelData>,
+    command_sender: Sender<EmulatorCommand>,
+    /// Save state slot used by the save and load hotkeys, selected with the number keys
+    state_slot: u8,
     pixels: Option<Pixels<'static>>,
     terminated: Arc<AtomicBool>,
     window: Option<Arc<Window>>,
@@ -119,6 +124,39 @@ impl ApplicationHandler for App {
                 self.terminated.store(true, Ordering::Relaxed);
             }
 
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key_code),
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let command = match (map_key_to_button(key_code), state) {
+                    (Some(button), ElementState::Pressed) => {
+                        EmulatorCommand::Joypad(JoypadEvent::Pressed(button))
+                    }
+                    (Some(button), ElementState::Released) => {
+                        EmulatorCommand::Joypad(JoypadEvent::Released(button))
+                    }
+                    // the rewind key is held to step backwards
+                    (None, state) if key_code == KeyCode::KeyR => {
+                        EmulatorCommand::Rewind(state.is_pressed())
+                    }
+                    (None, ElementState::Pressed) => match self.map_key_to_command(key_code) {
+                        Some(command) => command,
+                        None => return,
+                    },
+                    (None, ElementState::Released) => return,
+                };
+
+                if self.command_sender.send(command).is_err() {
+                    error!("Failed to send {command:?}, emulation thread has stopped");
+                }
+            }
+
             WindowEvent::Resized(size) => {
                 if self.pixels.is_none() {
                     return;
@@ -164,15 +202,36 @@ impl ApplicationHandler for App {
 }
 
 impl App {
-    pub fn init(terminated: Arc<AtomicBool>, frame_receiver: Receiver<PixelData>) -> Self {
+    pub fn init(
+        terminated: Arc<AtomicBool>,
+        frame_receiver: Receiver<PixelData>,
+        command_sender: Sender<EmulatorCommand>,
+    ) -> Self {
         Self {
             frame_receiver,
+            command_sender,
+            state_slot: 1,
             pixels: None,
             window: None,
             terminated: terminated.clone(),
         }
     }
 
+    /// Save state hotkeys: number keys select the slot, F5 saves and F8 loads the selected slot
+    fn map_key_to_command(&mut self, key_code: KeyCode) -> Option<EmulatorCommand> {
+        match key_code {
+            KeyCode::F5 => Some(EmulatorCommand::SaveState(self.state_slot)),
+            KeyCode::F8 => Some(EmulatorCommand::LoadState(self.state_slot)),
+            _ => {
+                let slot = map_key_to_digit(key_code)?;
+                self.state_slot = slot;
+                info!("Selected save state slot {slot}");
+
+                None
+            }
+        }
+    }
+
     pub fn run(&mut self) {
         let event_loop = EventLoop::new().expect("Failed to create event loop");
         event_loop.set_control_flow(ControlFlow::Wait);
@@ -180,6 +239,39 @@ impl App {
     }
 }
 
+/// Keyboard layout: arrow keys for the D-pad, X/Z for A/B, Enter for Start and Backspace for Select
+fn map_key_to_button(key_code: KeyCode) -> Option<Button> {
+    match key_code {
+        KeyCode::ArrowRight => Some(Button::Right),
+        KeyCode::ArrowLeft => Some(Button::Left),
+        KeyCode::ArrowUp => Some(Button::Up),
+        KeyCode::ArrowDown => Some(Button::Down),
+        KeyCode::KeyX => Some(Button::A),
+        KeyCode::KeyZ => Some(Button::B),
+        KeyCode::Backspace => Some(Button::Select),
+        KeyCode::Enter => Some(Button::Start),
+        _ => None,
+    }
+}
+
+fn map_key_to_digit(key_code: KeyCode) -> Option<u8> {
+    let digit = match key_code {
+        KeyCode::Digit0 => 0,
+        KeyCode::Digit1 => 1,
+        KeyCode::Digit2 => 2,
+        KeyCode::Digit3 => 3,
+        KeyCode::Digit4 => 4,
+        KeyCode::Digit5 => 5,
+        KeyCode::Digit6 => 6,
+        KeyCode::Digit7 => 7,
+        KeyCode::Digit8 => 8,
+        KeyCode::Digit9 => 9,
+        _ => return None,
+    };
+
+    Some(digit)
+}
+
 fn log_error<E: std::error::Error + 'static>(method_name: &str, err: E) {
     error!("{method_name}() failed: {err}");
     for source in err.sources().skip(1) {
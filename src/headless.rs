@@ -0,0 +1,159 @@
+use crate::emulator::{EmulatorState, CLOCK_SPEED};
+use crate::graphics::{PixelData, FRAME_CYCLES};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Register values of mooneye's test roms after a passed test: B, C, D, E, H, L
+const PASSED_FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// Ends a headless run before its frame or time limit
+#[derive(Clone, Debug)]
+pub enum StopCondition {
+    /// The serial output contains the text, which passes the run
+    Serial(String),
+    /// `LD B,B` was executed, passing the run if B-L hold the fibonacci numbers of mooneye's test
+    /// roms
+    Breakpoint,
+}
+
+/// `serial:<text>` or `breakpoint`
+impl FromStr for StopCondition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("serial", text)) => Ok(Self::Serial(text.to_string())),
+            None if value == "breakpoint" => Ok(Self::Breakpoint),
+            _ => Err(format!(
+                "Expected serial:<text> or breakpoint, got '{value}'"
+            )),
+        }
+    }
+}
+
+/// Runs the emulator as fast as possible without a window, until the first limit is reached
+#[derive(Clone, Debug, Default)]
+pub struct HeadlessConfig {
+    pub frames: Option<u32>,
+    /// Emulated time, not wall clock time
+    pub seconds: Option<f64>,
+    pub until: Option<StopCondition>,
+    /// Writes the last frame to a PNG file once the run is over
+    pub screenshot: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeadlessResult {
+    /// The stop condition was met, or the limit was reached without a stop condition
+    Passed,
+    /// The breakpoint was hit without the passing register values
+    Failed,
+    /// The limit was reached before the stop condition was met
+    TimedOut,
+}
+
+impl HeadlessResult {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Passed => 0,
+            Self::Failed => 1,
+            Self::TimedOut => 2,
+        }
+    }
+}
+
+pub(crate) struct HeadlessRun {
+    config: HeadlessConfig,
+    cycle_limit: Option<u64>,
+    cycles: u64,
+    serial_output: String,
+    last_frame: PixelData,
+}
+
+impl HeadlessRun {
+    pub(crate) fn new(config: HeadlessConfig) -> Self {
+        let frame_limit = config
+            .frames
+            .map(|frames| u64::from(frames) * u64::from(FRAME_CYCLES));
+        let time_limit = config
+            .seconds
+            .map(|seconds| (seconds * f64::from(CLOCK_SPEED)) as u64);
+
+        let cycle_limit = match (frame_limit, time_limit) {
+            (Some(frame_limit), Some(time_limit)) => Some(frame_limit.min(time_limit)),
+            (frame_limit, time_limit) => frame_limit.or(time_limit),
+        };
+
+        Self {
+            config,
+            cycle_limit,
+            cycles: 0,
+            serial_output: String::new(),
+            last_frame: PixelData::default(),
+        }
+    }
+
+    /// Called after every step of the emulator, returns the result once the run is over
+    pub(crate) fn step(&mut self, state: &EmulatorState, cycles: u8) -> Option<HeadlessResult> {
+        self.cycles += u64::from(cycles);
+
+        if let Some(framebuffer) = state.framebuffer {
+            self.last_frame = framebuffer;
+        }
+
+        if matches!(self.config.until, Some(StopCondition::Breakpoint))
+            && state.cpu.hit_breakpoint()
+        {
+            let registers = &state.cpu.registers;
+            let values = [
+                registers.b,
+                registers.c,
+                registers.d,
+                registers.e,
+                registers.h,
+                registers.l,
+            ];
+
+            return Some(if values == PASSED_FIBONACCI {
+                HeadlessResult::Passed
+            } else {
+                HeadlessResult::Failed
+            });
+        }
+
+        match self.cycle_limit {
+            Some(limit) if self.cycles >= limit => Some(match self.config.until {
+                Some(_) => HeadlessResult::TimedOut,
+                None => HeadlessResult::Passed,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Collects the serial output, returns the result once it contains the expected text
+    pub(crate) fn receive_serial(&mut self, bytes: &[u8]) -> Option<HeadlessResult> {
+        self.serial_output.push_str(&String::from_utf8_lossy(bytes));
+
+        match &self.config.until {
+            Some(StopCondition::Serial(text)) if self.serial_output.contains(text.as_str()) => {
+                Some(HeadlessResult::Passed)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn finish(&self, result: HeadlessResult) -> Result<HeadlessResult> {
+        if let Some(path) = &self.config.screenshot {
+            self.last_frame.save_png(path)?;
+            log::info!("Saved screenshot to {}", path.display());
+        }
+
+        log::info!(
+            "Headless run finished after {} cycles: {result:?}",
+            self.cycles
+        );
+
+        Ok(result)
+    }
+}
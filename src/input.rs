@@ -0,0 +1,178 @@
+use crate::emulator::EmulatorState;
+use crate::memory::joypad::{Button, JoypadEvent};
+use anyhow::{Context, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Pressed buttons from the given T-cycle on, until the next event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub cycle: u64,
+    pub pressed: Vec<Button>,
+}
+
+/// Button changes at fixed points in emulated time. Cycles are counted from when the script is
+/// attached with `GameBoy::set_input_script`, which makes playback independent of the host.
+///
+/// The text format has one event per line: the cycle followed by the pressed buttons, e.g.
+/// `70224 A START`. A cycle without buttons releases all of them, `#` starts a comment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    /// Sorted by cycle
+    events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    /// Adds an event, events at the same cycle replace each other
+    pub fn push(&mut self, cycle: u64, pressed: &[Button]) {
+        let event = InputEvent {
+            cycle,
+            pressed: pressed.to_vec(),
+        };
+
+        match self
+            .events
+            .binary_search_by_key(&cycle, |event| event.cycle)
+        {
+            Ok(index) => self.events[index] = event,
+            Err(index) => self.events.insert(index, event),
+        }
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    /// Buttons of the latest event at or before the cycle, `None` if nothing changes there
+    pub(crate) fn pressed_at(&self, cycle: u64, next_event: &mut usize) -> Option<&[Button]> {
+        let mut pressed = None;
+
+        while let Some(event) = self.events.get(*next_event) {
+            if event.cycle > cycle {
+                break;
+            }
+
+            pressed = Some(event.pressed.as_slice());
+            *next_event += 1;
+        }
+
+        pressed
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = anyhow::Error;
+
+    fn from_str(contents: &str) -> Result<Self> {
+        let mut script = Self::default();
+
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut fields = line.split_whitespace();
+
+            let Some(cycle) = fields.next() else {
+                continue;
+            };
+
+            let parse_event = || -> Result<(u64, Vec<Button>)> {
+                let cycle = cycle.parse().context("Invalid cycle")?;
+                let pressed = fields
+                    .map(|button| button.parse().map_err(anyhow::Error::msg))
+                    .collect::<Result<_>>()?;
+
+                Ok((cycle, pressed))
+            };
+
+            let (cycle, pressed) =
+                parse_event().with_context(|| format!("Line {}: '{line}'", number + 1))?;
+            script.push(cycle, &pressed);
+        }
+
+        Ok(script)
+    }
+}
+
+impl fmt::Display for InputScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            write!(f, "{}", event.cycle)?;
+
+            for button in &event.pressed {
+                write!(f, " {button}")?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Plays an input script back while stepping
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InputPlayer {
+    script: InputScript,
+    /// T-cycles since playback started
+    cycles: u64,
+    next_event: usize,
+}
+
+impl InputPlayer {
+    pub(crate) fn new(script: InputScript) -> Self {
+        Self {
+            script,
+            cycles: 0,
+            next_event: 0,
+        }
+    }
+
+    pub(crate) fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Applies the events due before the next instruction, then executes it
+    pub(crate) fn step(&mut self, state: &mut EmulatorState) -> u8 {
+        if let Some(pressed) = self.script.pressed_at(self.cycles, &mut self.next_event) {
+            state.cpu.bus.set_buttons(pressed);
+        }
+
+        let cycles = state.step();
+        self.cycles += u64::from(cycles);
+
+        cycles
+    }
+}
+
+/// Records joypad changes with the T-cycle they happen at, for playback with `InputPlayer`
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InputRecorder {
+    script: InputScript,
+    /// T-cycles since recording started
+    cycles: u64,
+}
+
+impl InputRecorder {
+    pub(crate) fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub(crate) fn add_cycles(&mut self, cycles: u8) {
+        self.cycles += u64::from(cycles);
+    }
+
+    /// Records the buttons pressed after the event. These are taken from the joypad, which may
+    /// differ from the host keys after loading a state.
+    pub(crate) fn handle_joypad_event(&mut self, state: &mut EmulatorState, event: JoypadEvent) {
+        state.handle_joypad_event(event);
+        self.record(state);
+    }
+
+    pub(crate) fn record(&mut self, state: &EmulatorState) {
+        self.script
+            .push(self.cycles, &state.cpu.bus.pressed_buttons());
+    }
+
+    pub(crate) fn into_script(self) -> InputScript {
+        self.script
+    }
+}
@@ -0,0 +1,42 @@
+#![allow(clippy::upper_case_acronyms)]
+//! Game Boy (DMG) emulator. `GameBoy` is the core for embedding, movies of recorded inputs are
+//! played back with `Movie`.
+
+mod apu;
+mod cpu;
+mod emulator;
+mod gameboy;
+mod graphics;
+mod headless;
+mod input;
+mod link;
+mod memory;
+mod movie;
+mod printer;
+mod rewind;
+mod save_state;
+#[cfg(feature = "debugger")]
+mod tui;
+
+pub use apu::{AUDIO_CHANNELS, DEFAULT_SAMPLE_RATE};
+pub use emulator::CLOCK_SPEED;
+pub use gameboy::{CpuRegisters, GameBoy};
+pub use graphics::{PixelData, GRAYSCALE_SHADES, LCD_HEIGHT, LCD_WIDTH};
+pub use input::{InputEvent, InputScript};
+pub use memory::cartridge::RtcClock;
+pub use memory::joypad::Button;
+pub use memory::serial::{link_cable, CaptureBuffer, LinkPort, SerialDevice, SharedSerialDevice};
+pub use movie::{Movie, MovieStart};
+
+/// The real-time emulator and its frontends, used by the `gb-emulator` binary. Not part of the
+/// library api.
+#[doc(hidden)]
+pub mod frontend {
+    pub use crate::emulator::{battery_save_path, Emulator, EmulatorConfig};
+    pub use crate::headless::{HeadlessConfig, StopCondition};
+    pub use crate::link::{LinkConfig, SocketLink};
+    pub use crate::movie::MovieMode;
+    pub use crate::printer::GameBoyPrinter;
+    #[cfg(feature = "debugger")]
+    pub use crate::tui::Debugger;
+}
@@ -0,0 +1,255 @@
+use crate::memory::serial::SerialDevice;
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Sent by both sides when connecting, followed by the protocol version
+const LINK_MAGIC: &[u8; 4] = b"GBLK";
+const LINK_VERSION: u8 = 1;
+/// Both sides exchange a sync message after this many T-cycles and wait for the message of the
+/// other side. A multiple of the 512 T-cycles of one serial clock.
+const LINK_SYNC_CYCLES: u32 = 2048;
+const LINK_MESSAGE_SIZE: usize = 3;
+const LINK_FLAG_WAITING: u8 = 0b01;
+const LINK_FLAG_TRANSFER: u8 = 0b10;
+const SERIAL_IDLE_BYTE: u8 = 0xFF;
+
+#[derive(Clone, Debug)]
+pub enum LinkAddress {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// `listen:<addr>` or `connect:<addr>`, where `<addr>` is `host:port` or `unix:<path>`
+#[derive(Clone, Debug)]
+pub enum LinkConfig {
+    Listen(LinkAddress),
+    Connect(LinkAddress),
+}
+
+impl FromStr for LinkAddress {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        match address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => Ok(Self::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            Some(_) => Err("Unix sockets are not supported on this platform".to_string()),
+            None => Ok(Self::Tcp(address.to_string())),
+        }
+    }
+}
+
+impl FromStr for LinkConfig {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("listen", address)) => Ok(Self::Listen(address.parse()?)),
+            Some(("connect", address)) => Ok(Self::Connect(address.parse()?)),
+            _ => Err(format!(
+                "Expected listen:<addr> or connect:<addr>, got '{value}'"
+            )),
+        }
+    }
+}
+
+trait LinkStream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> LinkStream for T {}
+
+/// Link cable to another emulator process over a local socket.
+///
+/// Both emulators run in lockstep: every `LINK_SYNC_CYCLES` each side sends whether it waits for
+/// an external clock (with its SB) and the byte it shifted out as master, then blocks until the
+/// message of the other side arrives. Transfers only depend on the state exchanged at these sync
+/// points, which keeps both sides deterministic regardless of network timing.
+pub struct SocketLink {
+    /// `None` once the connection was lost, the port then behaves like a disconnected one
+    stream: Option<Box<dyn LinkStream>>,
+    cycles: u32,
+    /// SB of this side while waiting for an external clock, sent at the next sync point
+    waiting: Option<u8>,
+    /// Byte shifted out with the internal clock since the last sync point
+    transfer: Option<u8>,
+    /// SB of the other side if it was waiting for an external clock at the last sync point
+    peer_waiting: Option<u8>,
+    /// Byte the other side shifted out to this side as master
+    peer_transfer: Option<u8>,
+}
+
+impl SocketLink {
+    /// Blocks until the other emulator is connected
+    pub fn open(config: &LinkConfig) -> Result<Self> {
+        let mut stream = match config {
+            LinkConfig::Listen(address) => accept(address)?,
+            LinkConfig::Connect(address) => connect(address)?,
+        };
+
+        handshake(&mut stream)?;
+        log::info!("Link cable connected");
+
+        Ok(Self {
+            stream: Some(stream),
+            cycles: 0,
+            waiting: None,
+            transfer: None,
+            peer_waiting: None,
+            peer_transfer: None,
+        })
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(());
+        };
+
+        let mut flags = 0;
+
+        if self.waiting.is_some() {
+            flags |= LINK_FLAG_WAITING;
+        }
+
+        if self.transfer.is_some() {
+            flags |= LINK_FLAG_TRANSFER;
+        }
+
+        let message = [
+            flags,
+            self.waiting.take().unwrap_or(SERIAL_IDLE_BYTE),
+            self.transfer.take().unwrap_or(SERIAL_IDLE_BYTE),
+        ];
+
+        stream.write_all(&message)?;
+        stream.flush()?;
+
+        let mut message = [0; LINK_MESSAGE_SIZE];
+        stream.read_exact(&mut message)?;
+
+        let [flags, waiting, transfer] = message;
+
+        self.peer_waiting = (flags & LINK_FLAG_WAITING != 0).then_some(waiting);
+
+        if flags & LINK_FLAG_TRANSFER != 0 {
+            self.peer_transfer = Some(transfer);
+        }
+
+        Ok(())
+    }
+}
+
+impl SerialDevice for SocketLink {
+    /// The other side only receives the byte if it was waiting for an external clock at the last
+    /// sync point, it is delivered after the next one
+    fn exchange(&mut self, byte: u8) -> u8 {
+        match self.peer_waiting.take() {
+            Some(peer_byte) => {
+                self.transfer = Some(byte);
+                peer_byte
+            }
+            None => SERIAL_IDLE_BYTE,
+        }
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        let received = self.peer_transfer.take();
+        self.waiting = received.is_none().then_some(byte);
+
+        received
+    }
+
+    fn step(&mut self, t_cycles: u8) {
+        self.cycles += u32::from(t_cycles);
+
+        if self.cycles < LINK_SYNC_CYCLES {
+            return;
+        }
+
+        self.cycles -= LINK_SYNC_CYCLES;
+
+        if let Err(error) = self.sync() {
+            log::error!("Link cable disconnected: {error}");
+            self.stream = None;
+            self.peer_waiting = None;
+            self.peer_transfer = None;
+        }
+    }
+}
+
+fn accept(address: &LinkAddress) -> Result<Box<dyn LinkStream>> {
+    match address {
+        LinkAddress::Tcp(address) => {
+            let listener = TcpListener::bind(address)
+                .with_context(|| format!("Failed to listen on {address}"))?;
+            log::info!("Waiting for link partner on {address}");
+
+            let (stream, _) = listener.accept()?;
+            stream.set_nodelay(true)?;
+
+            Ok(Box::new(stream))
+        }
+        #[cfg(unix)]
+        LinkAddress::Unix(path) => {
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("Failed to listen on {}", path.display()))?;
+            log::info!("Waiting for link partner on {}", path.display());
+
+            let (stream, _) = listener.accept()?;
+            // the socket file is not needed anymore once connected
+            let _ = std::fs::remove_file(path);
+
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+fn connect(address: &LinkAddress) -> Result<Box<dyn LinkStream>> {
+    match address {
+        LinkAddress::Tcp(address) => {
+            let stream = TcpStream::connect(address)
+                .with_context(|| format!("Failed to connect to {address}"))?;
+            stream.set_nodelay(true)?;
+
+            Ok(Box::new(stream))
+        }
+        #[cfg(unix)]
+        LinkAddress::Unix(path) => {
+            let stream = UnixStream::connect(path)
+                .with_context(|| format!("Failed to connect to {}", path.display()))?;
+
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+fn handshake(stream: &mut Box<dyn LinkStream>) -> Result<()> {
+    stream.write_all(LINK_MAGIC)?;
+    stream.write_all(&[LINK_VERSION])?;
+    stream.flush()?;
+
+    let mut hello = [0; 5];
+    stream
+        .read_exact(&mut hello)
+        .context("Link partner closed the connection")?;
+
+    if &hello[..4] != LINK_MAGIC {
+        bail!("Link partner is not a gb-emulator");
+    }
+
+    if hello[4] != LINK_VERSION {
+        bail!(
+            "Link partner uses protocol version {}, expected {LINK_VERSION}",
+            hello[4]
+        );
+    }
+
+    Ok(())
+}
@@ -1,16 +1,16 @@
-#![allow(clippy::upper_case_acronyms)]
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
-use emulator::Emulator;
+#[cfg(feature = "debugger")]
+use gb_emulator::frontend::Debugger;
+use gb_emulator::frontend::{
+    battery_save_path, Emulator, EmulatorConfig, GameBoyPrinter, HeadlessConfig, LinkConfig,
+    MovieMode, SocketLink, StopCondition,
+};
+use gb_emulator::{Movie, RtcClock};
 use std::fs;
 use std::path::PathBuf;
-use tui::Debugger;
-
-mod cpu;
-mod emulator;
-mod graphics;
-mod memory;
-mod tui;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
 
 const PATH_DMG_BOOT_ROM: &str = "./boot/dmg.bin";
 
@@ -25,42 +25,145 @@ struct Cli {
     boot: bool,
 
     /// Display the TUI debugger
-    #[arg(short = 'd', long)]
+    #[arg(short = 'd', long, conflicts_with = "headless")]
     open_debugger: bool,
 
     /// Starts the emulator in paused state
-    #[arg(short = 'p', long)]
+    #[arg(short = 'p', long, conflicts_with = "headless")]
     pause: bool,
+
+    /// Drive the cartridge real-time clock by the host time instead of emulated cycles
+    #[arg(long)]
+    rtc_wall_clock: bool,
+
+    /// Connect the link cable to another instance: listen:<addr> or connect:<addr>, where <addr>
+    /// is host:port or unix:<path>
+    #[arg(long, value_name = "MODE:ADDR")]
+    link: Option<LinkConfig>,
+
+    /// Attach a Game Boy Printer to the link port, printed images are saved as PNG files in <DIR>
+    #[arg(long, value_name = "DIR", conflicts_with = "link")]
+    printer: Option<PathBuf>,
+
+    /// Run without a window as fast as possible until --frames, --seconds or --until is reached.
+    /// Exits with 0 if passed, 1 if failed and 2 on timeout.
+    #[arg(long)]
+    headless: bool,
+
+    /// Stop a headless run after this many frames
+    #[arg(long, requires = "headless")]
+    frames: Option<u32>,
+
+    /// Stop a headless run after this many seconds of emulated time
+    #[arg(long, requires = "headless")]
+    seconds: Option<f64>,
+
+    /// Stop a headless run once the condition is met: serial:<text> or breakpoint (LD B,B)
+    #[arg(long, value_name = "CONDITION", requires = "headless")]
+    until: Option<StopCondition>,
+
+    /// Save the last frame of a headless run as PNG
+    #[arg(long, value_name = "PATH", requires = "headless")]
+    screenshot: Option<PathBuf>,
+
+    /// Record all inputs into a movie file, which is written on exit. Loading a state restarts the
+    /// recording from it.
+    #[arg(long, value_name = "MOVIE", conflicts_with_all = ["boot", "rtc_wall_clock", "link"])]
+    record: Option<PathBuf>,
+
+    /// Play back a recorded movie, host input is ignored until it ends
+    #[arg(
+        long,
+        value_name = "MOVIE",
+        conflicts_with_all = ["boot", "rtc_wall_clock", "link", "record"]
+    )]
+    play: Option<PathBuf>,
 }
 
+#[cfg_attr(not(feature = "debugger"), allow(unused_variables))]
 fn init_logging(use_tui_debugger: bool) {
-    use log::LevelFilter;
-    use tui_logger::{init_logger, set_default_level};
-
+    #[cfg(feature = "debugger")]
     if use_tui_debugger {
+        use log::LevelFilter;
+        use tui_logger::{init_logger, set_default_level};
+
         init_logger(LevelFilter::Info).unwrap();
         set_default_level(LevelFilter::Info);
-    } else {
-        env_logger::init();
+        return;
     }
+
+    env_logger::init();
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     color_eyre::install().unwrap();
 
     let cli = Cli::parse();
 
+    if cfg!(not(feature = "debugger")) && cli.open_debugger {
+        bail!("Built without the debugger feature");
+    }
+
+    if cli.headless && cli.frames.is_none() && cli.seconds.is_none() && cli.until.is_none() {
+        bail!("Headless mode needs --frames, --seconds or --until");
+    }
+
     init_logging(cli.open_debugger);
 
-    let cartridge_contents = fs::read(cli.rom).context("Failed to read game rom.")?;
+    let cartridge_contents = fs::read(&cli.rom).context("Failed to read game rom.")?;
+
+    let save_path = battery_save_path(&cli.rom);
+    let save_contents = save_path
+        .exists()
+        .then(|| fs::read(&save_path).context("Failed to read save file."))
+        .transpose()?;
 
     let boot_contents = cli
         .boot
         .then(|| fs::read(PATH_DMG_BOOT_ROM).context("Failed to read binary rom."))
         .transpose()?;
 
-    let mut emulator = Emulator::init(boot_contents.as_deref(), &cartridge_contents, cli.pause)?;
-
+    let rtc_clock = if cli.rtc_wall_clock {
+        RtcClock::WallClock
+    } else {
+        RtcClock::Emulated
+    };
+
+    let serial_device = match (&cli.link, &cli.printer) {
+        (Some(config), _) => Some(Arc::new(Mutex::new(SocketLink::open(config)?)) as _),
+        (None, Some(output_dir)) => {
+            fs::create_dir_all(output_dir).context("Failed to create printer output directory.")?;
+            Some(Arc::new(Mutex::new(GameBoyPrinter::new(output_dir))) as _)
+        }
+        (None, None) => None,
+    };
+
+    let movie = match (cli.record, &cli.play) {
+        (Some(path), _) => Some(MovieMode::Record(path)),
+        (None, Some(path)) => Some(MovieMode::Play(Movie::load(path)?)),
+        (None, None) => None,
+    };
+
+    let mut emulator = Emulator::init(
+        &cartridge_contents,
+        EmulatorConfig {
+            boot_contents: boot_contents.as_deref(),
+            paused: cli.pause,
+            rtc_clock,
+            rom_path: Some(&cli.rom),
+            save_contents: save_contents.as_deref(),
+            serial_device,
+            headless: cli.headless.then_some(HeadlessConfig {
+                frames: cli.frames,
+                seconds: cli.seconds,
+                until: cli.until,
+                screenshot: cli.screenshot,
+            }),
+            movie,
+        },
+    )?;
+
+    #[cfg(feature = "debugger")]
     let debugger = cli
         .open_debugger
         .then(|| Debugger::new(&emulator.state, &emulator.terminated, &emulator.paused));
@@ -68,9 +171,14 @@ fn main() -> Result<()> {
     // start main emulation loop
     emulator.start();
 
-    debugger.map(|d| d.shutdown());
+    #[cfg(feature = "debugger")]
+    if let Some(debugger) = debugger {
+        debugger.shutdown();
+    }
 
-    emulator.emulation_thread.join().unwrap();
+    let headless_result = emulator.shutdown()?;
 
-    Ok(())
+    Ok(headless_result.map_or(ExitCode::SUCCESS, |result| {
+        ExitCode::from(result.exit_code())
+    }))
 }
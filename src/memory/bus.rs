@@ -1,16 +1,23 @@
 #![allow(unused)]
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
 use std::path::Iter;
 
-use super::mem::{Addressible, Memory};
+use super::cartridge::{Cartridge, CartridgeEvent, Mapper, RtcClock};
+use super::joypad::Joypad;
+use super::mem::Addressible;
+use super::serial::{disconnected, SharedSerialDevice};
+use crate::apu::{APU, DEFAULT_SAMPLE_RATE};
 use crate::graphics::PPUMode;
+use anyhow::Result;
 
 pub const BUS_SIZE: usize = 0xFFFF + 1;
 const ROM_BANK_0_START: u16 = 0x0000;
 const ROM_BANK_0_END: u16 = 0x3FFF;
 const ROM_BANK_1_START: u16 = 0x4000;
 const ROM_BANK_1_END: u16 = 0x7FFF;
-const VRAM_START: u16 = 0x8000;
-const VRAM_END: u16 = 0x9FFF;
+pub const VRAM_START: u16 = 0x8000;
+pub const VRAM_END: u16 = 0x9FFF;
 const VRAM_SIZE: usize = (VRAM_END - VRAM_START + 1) as usize;
 const EXTERNAL_RAM_START: u16 = 0xA000;
 const EXTERNAL_RAM_END: u16 = 0xBFFF;
@@ -18,9 +25,14 @@ const EXTERNAL_RAM_SIZE: usize = (EXTERNAL_RAM_END - EXTERNAL_RAM_START + 1) as
 const WRAM_START: u16 = 0xC000;
 const WRAM_END: u16 = 0xDFFF;
 const WRAM_SIZE: usize = (WRAM_END - WRAM_START + 1) as usize;
+/// Mirror of 0xC000-0xDDFF
+const ECHO_RAM_START: u16 = 0xE000;
+const ECHO_RAM_END: u16 = 0xFDFF;
 pub const OAM_START: u16 = 0xFE00;
-const OAM_END: u16 = 0xFEFF;
+pub const OAM_END: u16 = 0xFEFF;
 const OAM_SIZE: usize = (OAM_END - OAM_START + 1) as usize;
+const AUDIO_START: u16 = 0xFF10;
+const AUDIO_END: u16 = 0xFF3F;
 const HRAM_START: u16 = 0xFF80;
 const HRAM_END: u16 = 0xFFFE;
 const HRAM_SIZE: usize = (HRAM_END - HRAM_START + 1) as usize;
@@ -28,19 +40,24 @@ const BOOT_ROM_LENGTH: u16 = 0x0100;
 
 pub(super) const BYTE_INVALID_READ: u8 = 0xFF;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Bus {
-    cartridge: Memory,
+    cartridge: Cartridge,
     vram: Addressible<VRAM_SIZE>,
     wram: Addressible<WRAM_SIZE>,
-    // TODO: OAM DMA transfer https://gbdev.io/pandocs/OAM_DMA_Transfer.html#oam-dma-transfer
     pub oam: Addressible<OAM_SIZE>,
     pub(super) io: IORegisters,
+    pub(super) joypad: Joypad,
+    pub(crate) apu: APU,
     hram: Addressible<HRAM_SIZE>,
     pub ppu_mode: PPUMode,
     /// boot rom is saved in separate space, as it is unmapped after boot and saved inside the CPU
+    #[serde(with = "BigArray")]
     boot_rom: [u8; BOOT_ROM_LENGTH as usize],
     pub boot_rom_disabled: bool,
+    /// Device attached to the serial port, save states keep the one of the running emulator
+    #[serde(skip, default = "disconnected")]
+    pub(super) serial_device: SharedSerialDevice,
 }
 
 pub const CARTRIDGE_TYPE: u16 = 0x0147;
@@ -75,6 +92,12 @@ pub const LCD_Y: u16 = 0xFF44;
 pub const LCD_Y_COMPARE: u16 = 0xFF45;
 /// OAM DMA source address and start trigger
 pub const DMA_START: u16 = 0xFF46;
+/// BGP register address
+pub const BG_PALETTE: u16 = 0xFF47;
+/// OBP0 register address
+pub const OBJ_PALETTE_0: u16 = 0xFF48;
+/// OBP1 register address
+pub const OBJ_PALETTE_1: u16 = 0xFF49;
 /// WY register address
 pub const WINDOW_Y: u16 = 0xFF4A;
 /// WX register address
@@ -85,15 +108,24 @@ pub const SCROLL_Y: u16 = 0xFF42;
 pub const SCROLL_X: u16 = 0xFF43;
 const BOOT_DISABLE: u16 = 0xFF50;
 
-#[derive(Default, Clone, Copy)]
+/// STAT bit 7 is unused and always reads as set
+const LCD_STAT_UNUSED_BIT: u8 = 0b10000000;
+
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub(super) struct IORegisters {
-    pub(super) joypad: u8,
     pub(super) serial_data: u8,
     pub(super) serial_control: u8,
-    pub(super) timer_divider: u8,
+    /// Bits shifted in the current serial transfer
+    pub(super) serial_bits: u8,
+    /// Internal 16-bit counter, DIV is its upper byte
+    pub(super) system_counter: u16,
     pub(super) timer_counter: u8,
     pub(super) timer_modulo: u8,
     pub(super) timer_control: u8,
+    /// TIMA overflowed in the last M-cycle, TMA is loaded in the next one
+    pub(super) tima_overflow: bool,
+    /// TMA was loaded into TIMA in the current M-cycle
+    pub(super) tima_reloaded: bool,
     pub(super) interrupt_requests: u8,
     pub(super) interrupt_enable: u8,
     // TODO: audio
@@ -101,9 +133,14 @@ pub(super) struct IORegisters {
     pub(super) boot_rom_disable: u8,
     pub(super) lcd_control: u8,
     pub(super) lcd_stat: u8,
+    /// Internal STAT interrupt line, all enabled STAT sources are OR'd together
+    pub(super) stat_line: bool,
     pub(super) lcd_y: u8,
     pub(super) lcd_y_compare: u8,
     pub(super) dma_start: u8,
+    pub(super) bg_palette: u8,
+    pub(super) obj_palette_0: u8,
+    pub(super) obj_palette_1: u8,
     pub(super) window_y: u8,
     pub(super) window_x: u8,
     pub(super) scroll_y: u8,
@@ -111,18 +148,21 @@ pub(super) struct IORegisters {
 }
 
 impl Bus {
-    pub fn from_cartridge(cartridge_contents: &[u8]) -> Self {
-        Self {
-            cartridge: Memory::init(cartridge_contents),
+    pub fn from_cartridge(cartridge_contents: &[u8]) -> Result<Self> {
+        Ok(Self {
+            cartridge: Cartridge::from_contents(cartridge_contents)?,
             vram: Addressible::default(),
             wram: Addressible::default(),
             oam: Addressible::default(),
             io: IORegisters::default(),
+            joypad: Joypad::default(),
+            apu: APU::init(DEFAULT_SAMPLE_RATE),
             hram: Addressible::default(),
             ppu_mode: PPUMode::default(),
             boot_rom: [0; BOOT_ROM_LENGTH as usize],
             boot_rom_disabled: false,
-        }
+            serial_device: disconnected(),
+        })
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
@@ -148,9 +188,7 @@ impl Bus {
                 self.cartridge.read_ram(address - EXTERNAL_RAM_START)
             }
             WRAM_START..=WRAM_END => self.wram.read(address - WRAM_START),
-            0xE000..=0xFDFF => {
-                unreachable!("Tried to access prohibited bus address 0x{address:04X}")
-            }
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram.read(address - ECHO_RAM_START),
             OAM_START..=OAM_END => {
                 if matches!(self.ppu_mode, PPUMode::OBJSearch | PPUMode::SendPixels) {
                     BYTE_INVALID_READ
@@ -159,20 +197,24 @@ impl Bus {
                 }
             }
             HRAM_START..=HRAM_END => self.hram.read(address - HRAM_START),
-            JOYP => self.io.joypad,
+            JOYP => self.joypad.read(),
             SERIAL_TRANSFER_DATA => self.io.serial_data,
-            SERIAL_TRANSFER_CONTROL => self.io.serial_control,
-            TIMER_DIVIDER => self.io.timer_divider,
+            SERIAL_TRANSFER_CONTROL => self.read_serial_control(),
+            TIMER_DIVIDER => self.get_timer_divider(),
             TIMER_COUNTER => self.io.timer_counter,
             TIMER_MODULO => self.io.timer_modulo,
             TIMER_CONTROL => self.io.timer_control,
             INTERRUPT_REQUESTS => self.io.interrupt_requests,
             INTERRUPT_ENABLE => self.io.interrupt_enable,
+            AUDIO_START..=AUDIO_END => self.apu.read(address),
             LCD_CONTROL => self.io.lcd_control,
-            LCD_STAT => self.io.lcd_stat,
+            LCD_STAT => self.io.lcd_stat | LCD_STAT_UNUSED_BIT,
             LCD_Y => self.io.lcd_y,
             LCD_Y_COMPARE => self.io.lcd_y_compare,
             DMA_START => self.io.dma_start,
+            BG_PALETTE => self.io.bg_palette,
+            OBJ_PALETTE_0 => self.io.obj_palette_0,
+            OBJ_PALETTE_1 => self.io.obj_palette_1,
             WINDOW_Y => self.io.window_y,
             WINDOW_X => self.io.window_x,
             SCROLL_Y => self.io.scroll_y,
@@ -188,41 +230,44 @@ impl Bus {
             // we don't check for the boot rom area here because the boot rom does not write in its
             // own address space
             ROM_BANK_0_START..=ROM_BANK_1_END => self.cartridge.write_rom(address, byte),
-            VRAM_START..=VRAM_END => {
-                if !matches!(self.ppu_mode, PPUMode::SendPixels) {
-                    self.vram.write(address - VRAM_START, byte)
-                }
+            VRAM_START..=VRAM_END if !matches!(self.ppu_mode, PPUMode::SendPixels) => {
+                self.vram.write(address - VRAM_START, byte)
             }
+            VRAM_START..=VRAM_END => {}
             EXTERNAL_RAM_START..=EXTERNAL_RAM_END => {
                 self.cartridge.write_ram(address - EXTERNAL_RAM_START, byte)
             }
             WRAM_START..=WRAM_END => self.wram.write(address - WRAM_START, byte),
-            0xE000..=0xFDFF => {
-                unreachable!("Tried to write prohibited bus address 0x{address:04X}")
-            }
-            OAM_START..=OAM_END => {
-                if !matches!(self.ppu_mode, PPUMode::OBJSearch | PPUMode::SendPixels) {
-                    self.oam.write(address - OAM_START, byte)
-                }
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram.write(address - ECHO_RAM_START, byte),
+            OAM_START..=OAM_END
+                if !matches!(self.ppu_mode, PPUMode::OBJSearch | PPUMode::SendPixels) =>
+            {
+                self.oam.write(address - OAM_START, byte)
             }
+            OAM_START..=OAM_END => {}
             HRAM_START..=HRAM_END => self.hram.write(address - HRAM_START, byte),
-            JOYP => self.io.joypad = byte,
+            JOYP => self.write_joypad(byte),
             SERIAL_TRANSFER_DATA => self.io.serial_data = byte,
-            SERIAL_TRANSFER_CONTROL => self.io.serial_control = byte,
-            TIMER_DIVIDER => self.io.timer_divider = byte,
-            TIMER_COUNTER => self.io.timer_counter = byte,
-            TIMER_MODULO => self.io.timer_modulo = byte,
-            TIMER_CONTROL => self.io.timer_control = byte,
+            SERIAL_TRANSFER_CONTROL => self.write_serial_control(byte),
+            TIMER_DIVIDER => self.write_timer_divider(),
+            TIMER_COUNTER => self.write_timer_counter(byte),
+            TIMER_MODULO => self.write_timer_modulo(byte),
+            TIMER_CONTROL => self.write_timer_control(byte),
             INTERRUPT_REQUESTS => self.io.interrupt_requests = byte,
             INTERRUPT_ENABLE => self.io.interrupt_enable = byte,
-            LCD_CONTROL => self.set_lcd_control(byte),
-            LCD_STAT => self.set_lcd_stat(byte),
-            LCD_Y => self.set_lcd_y(byte),
+            AUDIO_START..=AUDIO_END => self.apu.write(address, byte),
+            LCD_CONTROL => self.write_lcd_control(byte),
+            LCD_STAT => self.write_lcd_stat(byte),
+            // LY is read-only
+            LCD_Y => {}
             LCD_Y_COMPARE => {
                 self.io.lcd_y_compare = byte;
                 self.update_stat_lyc();
             }
             DMA_START => self.io.dma_start = byte,
+            BG_PALETTE => self.io.bg_palette = byte,
+            OBJ_PALETTE_0 => self.io.obj_palette_0 = byte,
+            OBJ_PALETTE_1 => self.io.obj_palette_1 = byte,
             WINDOW_Y => self.io.window_y = byte,
             WINDOW_X => self.io.window_x = byte,
             SCROLL_Y => self.io.scroll_y = byte,
@@ -244,6 +289,54 @@ impl Bus {
         }
     }
 
+    pub fn step_cartridge(&mut self, t_cycles: u8) {
+        self.cartridge.step(t_cycles);
+    }
+
+    /// Returns the events of cartridge hardware like the MBC5 rumble motor since the last call
+    pub fn drain_cartridge_events(&mut self) -> Vec<CartridgeEvent> {
+        self.cartridge.drain_events()
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.cartridge.set_rtc_clock(clock);
+    }
+
+    pub fn rom_checksum(&self) -> u32 {
+        self.cartridge.rom_checksum()
+    }
+
+    /// Save states do not contain the cartridge ROM, it is taken from the running bus instead
+    pub fn restore_rom(&mut self, running: &Bus) {
+        self.cartridge.restore_rom(&running.cartridge);
+    }
+
+    /// Save states do not contain the RTC clock mode, it is taken from the running bus instead
+    pub fn restore_rtc_clock(&mut self, running: &Bus) {
+        self.cartridge.restore_rtc_clock(&running.cartridge);
+    }
+
+    /// Keeps the serial device of the running bus connected when loading a save state
+    pub fn restore_serial_device(&mut self, running: &Bus) {
+        self.serial_device = running.serial_device.clone();
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.cartridge.has_battery()
+    }
+
+    pub fn is_cartridge_ram_dirty(&self) -> bool {
+        self.cartridge.is_ram_dirty()
+    }
+
+    pub fn battery_save(&mut self) -> Vec<u8> {
+        self.cartridge.battery_save()
+    }
+
+    pub fn load_battery_save(&mut self, contents: &[u8]) -> Result<()> {
+        self.cartridge.load_battery_save(contents)
+    }
+
     pub fn update_ppu_mode(&mut self, mode: PPUMode) {
         self.ppu_mode = mode;
     }
@@ -276,21 +369,26 @@ impl Bus {
                 self.cartridge.read_ram(address - EXTERNAL_RAM_START)
             }
             WRAM_START..=WRAM_END => self.wram.read(address - WRAM_START),
+            ECHO_RAM_START..=ECHO_RAM_END => self.wram.read(address - ECHO_RAM_START),
             OAM_START..=OAM_END => self.oam.read(address - OAM_START),
             HRAM_START..=HRAM_END => self.hram.read(address - HRAM_START),
-            JOYP => self.io.joypad,
+            JOYP => self.joypad.read(),
             SERIAL_TRANSFER_DATA => self.io.serial_data,
-            SERIAL_TRANSFER_CONTROL => self.io.serial_control,
-            TIMER_DIVIDER => self.io.timer_divider,
+            SERIAL_TRANSFER_CONTROL => self.read_serial_control(),
+            TIMER_DIVIDER => self.get_timer_divider(),
             TIMER_COUNTER => self.io.timer_counter,
             TIMER_MODULO => self.io.timer_modulo,
             TIMER_CONTROL => self.io.timer_control,
             INTERRUPT_REQUESTS => self.io.interrupt_requests,
             INTERRUPT_ENABLE => self.io.interrupt_enable,
+            AUDIO_START..=AUDIO_END => self.apu.read(address),
             LCD_CONTROL => self.io.lcd_control,
-            LCD_STAT => self.io.lcd_stat,
+            LCD_STAT => self.io.lcd_stat | LCD_STAT_UNUSED_BIT,
             LCD_Y => self.io.lcd_y,
             LCD_Y_COMPARE => self.io.lcd_y_compare,
+            BG_PALETTE => self.io.bg_palette,
+            OBJ_PALETTE_0 => self.io.obj_palette_0,
+            OBJ_PALETTE_1 => self.io.obj_palette_1,
             WINDOW_Y => self.io.window_y,
             WINDOW_X => self.io.window_x,
             SCROLL_Y => self.io.scroll_y,
@@ -318,3 +416,33 @@ pub const fn set_bit_status(byte: u8, position: u8, status: bool) -> u8 {
         byte & !(1 << position)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 32 KiB ROM without mapper
+    fn bus() -> Bus {
+        Bus::from_cartridge(&[0; 0x8000]).unwrap()
+    }
+
+    #[test]
+    fn ly_is_read_only() {
+        let mut bus = bus();
+        bus.set_lcd_y(0x12);
+        bus.write_byte(LCD_Y, 0x34);
+
+        assert_eq!(bus.read_byte(LCD_Y), 0x12);
+    }
+
+    #[test]
+    fn echo_ram_mirrors_wram() {
+        let mut bus = bus();
+        bus.write_byte(0xC123, 0x12);
+        bus.write_byte(0xFDFF, 0x34);
+
+        assert_eq!(bus.read_byte(0xE123), 0x12);
+        assert_eq!(bus.read_byte(0xDDFF), 0x34);
+        assert_eq!(bus.read_debug(0xFDFF), 0x34);
+    }
+}
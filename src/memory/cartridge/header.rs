@@ -0,0 +1,161 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Memory bank controller variants, see https://gbdev.io/pandocs/MBCs.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapperKind {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+}
+
+/// Decoded cartridge type byte at 0x0147
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct CartridgeType {
+    pub mapper: MapperKind,
+    pub has_ram: bool,
+    pub has_battery: bool,
+    pub has_timer: bool,
+    pub has_rumble: bool,
+}
+
+impl CartridgeType {
+    const fn new(mapper: MapperKind) -> Self {
+        Self {
+            mapper,
+            has_ram: false,
+            has_battery: false,
+            has_timer: false,
+            has_rumble: false,
+        }
+    }
+
+    const fn ram(self) -> Self {
+        Self {
+            has_ram: true,
+            ..self
+        }
+    }
+
+    const fn battery(self) -> Self {
+        Self {
+            has_battery: true,
+            ..self
+        }
+    }
+
+    const fn timer(self) -> Self {
+        Self {
+            has_timer: true,
+            ..self
+        }
+    }
+
+    const fn rumble(self) -> Self {
+        Self {
+            has_rumble: true,
+            ..self
+        }
+    }
+
+    pub fn from_header(value: u8) -> Result<Self> {
+        use MapperKind::*;
+
+        let cartridge_type = match value {
+            0x00 => Self::new(RomOnly),
+            0x01 => Self::new(Mbc1),
+            0x02 => Self::new(Mbc1).ram(),
+            0x03 => Self::new(Mbc1).ram().battery(),
+            // MBC2 always has built-in RAM, independent of the RAM size in the header
+            0x05 => Self::new(Mbc2).ram(),
+            0x06 => Self::new(Mbc2).ram().battery(),
+            0x08 => Self::new(RomOnly).ram(),
+            0x09 => Self::new(RomOnly).ram().battery(),
+            0x0F => Self::new(Mbc3).timer().battery(),
+            0x10 => Self::new(Mbc3).timer().ram().battery(),
+            0x11 => Self::new(Mbc3),
+            0x12 => Self::new(Mbc3).ram(),
+            0x13 => Self::new(Mbc3).ram().battery(),
+            0x19 => Self::new(Mbc5),
+            0x1A => Self::new(Mbc5).ram(),
+            0x1B => Self::new(Mbc5).ram().battery(),
+            0x1C => Self::new(Mbc5).rumble(),
+            0x1D => Self::new(Mbc5).rumble().ram(),
+            0x1E => Self::new(Mbc5).rumble().ram().battery(),
+            0x0B..=0x0D => bail!("Unsupported cartridge type 0x{value:02X} (MMM01)"),
+            0x20 => bail!("Unsupported cartridge type 0x{value:02X} (MBC6)"),
+            0x22 => bail!("Unsupported cartridge type 0x{value:02X} (MBC7)"),
+            0xFC => bail!("Unsupported cartridge type 0x{value:02X} (Pocket Camera)"),
+            0xFD => bail!("Unsupported cartridge type 0x{value:02X} (TAMA5)"),
+            0xFE => bail!("Unsupported cartridge type 0x{value:02X} (HuC3)"),
+            0xFF => bail!("Unsupported cartridge type 0x{value:02X} (HuC1)"),
+            _ => bail!("Invalid cartridge header at [0147](Cartridge Type): 0x{value:02X}"),
+        };
+
+        Ok(cartridge_type)
+    }
+}
+
+pub(super) const ROM_BANK_SIZE: usize = 0x4000; // 16 KiB
+const ROM_DEFAULT_SIZE: usize = 0x8000; // 32 KiB
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(super) enum RomSize {
+    Unset,
+    Extended(usize, usize),
+}
+
+impl RomSize {
+    pub(super) fn from_header(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Unset),
+            1..=8 => Ok(Self::Extended(ROM_DEFAULT_SIZE << value, 2 << value)),
+            _ => bail!("Invalid cartridge header at [0148](ROM Size): 0x{value:02X}"),
+        }
+    }
+
+    pub(super) fn bytes(&self) -> usize {
+        match self {
+            Self::Unset => ROM_DEFAULT_SIZE,
+            Self::Extended(size, _) => *size,
+        }
+    }
+
+    pub(super) fn banks(&self) -> usize {
+        match self {
+            Self::Unset => 2,
+            Self::Extended(_, banks) => *banks,
+        }
+    }
+}
+
+pub(super) const RAM_BANK_SIZE: usize = 0x2000; // 8 KiB
+
+#[derive(Clone, Copy)]
+pub(super) enum RamSize {
+    Unset,
+    Extended(usize),
+}
+
+impl RamSize {
+    pub(super) fn from_header(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Unset),
+            2 => Ok(Self::Extended(RAM_BANK_SIZE)),
+            3 => Ok(Self::Extended(RAM_BANK_SIZE << 2)),
+            4 => Ok(Self::Extended(RAM_BANK_SIZE << 4)),
+            5 => Ok(Self::Extended(RAM_BANK_SIZE << 3)),
+            _ => bail!("Invalid cartridge header at [0149](RAM Size): 0x{value:02X}"),
+        }
+    }
+
+    pub(super) fn bytes(&self) -> usize {
+        match self {
+            Self::Unset => 0,
+            Self::Extended(size) => *size,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::{CartridgeMemory, Mapper, ROM_BANK_SIZE};
+use crate::memory::bus::BYTE_INVALID_READ;
+use serde::{Deserialize, Serialize};
+
+const RAM_ENABLE_END: u16 = 0x1FFF;
+const RAM_ENABLE_VALUE: u8 = 0x0A;
+const ROM_BANK_NUMBER_START: u16 = 0x2000;
+const ROM_BANK_NUMBER_END: u16 = 0x3FFF;
+const RAM_BANK_NUMBER_START: u16 = 0x4000;
+const RAM_BANK_NUMBER_END: u16 = 0x5FFF;
+const BANKING_MODE_SELECT_START: u16 = 0x6000;
+const BANKING_MODE_SELECT_END: u16 = 0x7FFF;
+
+/// Size of MBC1M multicart ROMs, which are wired differently than regular 1 MiB MBC1 ROMs
+const MBC1_MULTICART_ROM_SIZE: usize = 0x100000;
+const MBC1_MULTICART_GAME_BANKS: usize = 0x10;
+const NINTENDO_LOGO_START: usize = 0x0104;
+const NINTENDO_LOGO_END: usize = 0x0133;
+
+/// MBC1 banking mode, see https://gbdev.io/pandocs/MBC1.html#60007fff--banking-mode-select-write-only
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+enum BankingMode {
+    /// 0x0000-0x3FFF and 0xA000-0xBFFF are locked to bank 0
+    #[default]
+    Simple,
+    /// The BANK2 register also switches 0x0000-0x3FFF and the RAM bank
+    Advanced,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc1 {
+    pub(super) memory: CartridgeMemory,
+    ram_enabled: bool,
+    /// BANK1 register: lower 5 bits of the ROM bank number, 0 is treated as 1
+    bank1: u8,
+    /// BANK2 register: upper 2 bits of the ROM bank number or RAM bank number
+    bank2: u8,
+    banking_mode: BankingMode,
+    /// MBC1M multicarts only use 4 bits of BANK1, BANK2 selects one of four 256 KiB games
+    multicart: bool,
+}
+
+/// MBC1M multicarts are detected by a second Nintendo logo at the start of the second game
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != MBC1_MULTICART_ROM_SIZE {
+        return false;
+    }
+
+    let logo = &rom[NINTENDO_LOGO_START..=NINTENDO_LOGO_END];
+    let offset = MBC1_MULTICART_GAME_BANKS * ROM_BANK_SIZE;
+
+    logo == &rom[offset + NINTENDO_LOGO_START..=offset + NINTENDO_LOGO_END]
+}
+
+impl Mbc1 {
+    pub(super) fn init(memory: CartridgeMemory) -> Self {
+        let multicart = is_multicart(&memory.rom);
+
+        if multicart {
+            log::info!("Detected MBC1M multicart");
+        }
+
+        Self {
+            memory,
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            banking_mode: BankingMode::default(),
+            multicart,
+        }
+    }
+
+    /// Number of bits BANK2 is shifted by to form the upper bits of the ROM bank number
+    fn bank2_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    /// ROM bank mapped to 0x0000-0x3FFF
+    fn lower_rom_bank(&self) -> usize {
+        match self.banking_mode {
+            BankingMode::Simple => 0,
+            BankingMode::Advanced => usize::from(self.bank2) << self.bank2_shift(),
+        }
+    }
+
+    /// ROM bank mapped to 0x4000-0x7FFF. Since only BANK1 is checked for 0, banks 0x20, 0x40 and
+    /// 0x60 can never be mapped here.
+    fn upper_rom_bank(&self) -> usize {
+        let bank1 = if self.multicart {
+            self.bank1 & 0x0F
+        } else {
+            self.bank1
+        };
+
+        (usize::from(self.bank2) << self.bank2_shift()) | usize::from(bank1)
+    }
+
+    fn ram_bank(&self) -> usize {
+        match self.banking_mode {
+            BankingMode::Simple => 0,
+            BankingMode::Advanced => usize::from(self.bank2),
+        }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => self.lower_rom_bank(),
+            _ => self.upper_rom_bank(),
+        };
+
+        self.memory.read_rom(bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, byte: u8) {
+        match address {
+            0..=RAM_ENABLE_END => {
+                self.ram_enabled = (byte & 0x0F) == RAM_ENABLE_VALUE;
+            }
+            ROM_BANK_NUMBER_START..=ROM_BANK_NUMBER_END => {
+                let bank_number = byte & 0x1F;
+
+                // bank 0 is not valid
+                self.bank1 = if bank_number == 0 { 1 } else { bank_number };
+            }
+            RAM_BANK_NUMBER_START..=RAM_BANK_NUMBER_END => {
+                self.bank2 = byte & 0x03;
+            }
+            BANKING_MODE_SELECT_START..=BANKING_MODE_SELECT_END => {
+                self.banking_mode = if byte & 0x01 == 0 {
+                    BankingMode::Simple
+                } else {
+                    BankingMode::Advanced
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return BYTE_INVALID_READ;
+        }
+
+        self.memory.read_ram(self.ram_bank(), address)
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        if self.ram_enabled {
+            self.memory.write_ram(self.ram_bank(), address, byte);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use super::{CartridgeMemory, Mapper};
+use crate::memory::bus::BYTE_INVALID_READ;
+use serde::{Deserialize, Serialize};
+
+const REGISTER_END: u16 = 0x3FFF;
+/// Address bit 8 selects between the RAM enable and the ROM bank register
+const REGISTER_SELECT_BIT: u16 = 0x0100;
+const RAM_ENABLE_VALUE: u8 = 0x0A;
+/// 512 half-bytes of built-in RAM, echoed through 0xA000-0xBFFF
+pub(super) const MBC2_RAM_SIZE: usize = 0x200;
+
+/// MBC2 with built-in 512x4 bit RAM, see https://gbdev.io/pandocs/MBC2.html
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc2 {
+    pub(super) memory: CartridgeMemory,
+    ram_enabled: bool,
+    /// 4-bit ROM bank number mapped to 0x4000-0x7FFF, 0 is treated as 1
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub(super) fn init(memory: CartridgeMemory) -> Self {
+        Self {
+            memory,
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank),
+        };
+
+        self.memory.read_rom(bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, byte: u8) {
+        match address {
+            0..=REGISTER_END if address & REGISTER_SELECT_BIT == 0 => {
+                self.ram_enabled = (byte & 0x0F) == RAM_ENABLE_VALUE;
+            }
+            0..=REGISTER_END => {
+                let bank_number = byte & 0x0F;
+
+                // bank 0 is not valid
+                self.rom_bank = if bank_number == 0 { 1 } else { bank_number };
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return BYTE_INVALID_READ;
+        }
+
+        // only the lower nibble is stored, the upper nibble is open bus
+        self.memory.read_ram(0, address) | 0xF0
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        if self.ram_enabled {
+            self.memory.write_ram(0, address, byte & 0x0F);
+        }
+    }
+}
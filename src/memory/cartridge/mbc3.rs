@@ -0,0 +1,134 @@
+use super::rtc::{Rtc, RtcClock, RtcRegister};
+use super::{CartridgeMemory, Mapper};
+use crate::memory::bus::BYTE_INVALID_READ;
+use serde::{Deserialize, Serialize};
+
+const RAM_ENABLE_END: u16 = 0x1FFF;
+const RAM_ENABLE_VALUE: u8 = 0x0A;
+const ROM_BANK_NUMBER_START: u16 = 0x2000;
+const ROM_BANK_NUMBER_END: u16 = 0x3FFF;
+const RAM_BANK_NUMBER_START: u16 = 0x4000;
+const RAM_BANK_NUMBER_END: u16 = 0x5FFF;
+const LATCH_CLOCK_DATA_START: u16 = 0x6000;
+const LATCH_CLOCK_DATA_END: u16 = 0x7FFF;
+
+/// MBC3 with optional real-time clock, see https://gbdev.io/pandocs/MBC3.html
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc3 {
+    pub(super) memory: CartridgeMemory,
+    /// Enables both RAM and RTC register access
+    ram_enabled: bool,
+    /// 7-bit ROM bank number mapped to 0x4000-0x7FFF, 0 is treated as 1
+    rom_bank: u8,
+    /// 0x00-0x03 select a RAM bank, 0x08-0x0C an RTC register
+    ram_bank_select: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub(super) fn init(memory: CartridgeMemory, has_timer: bool) -> Self {
+        Self {
+            memory,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank_select: 0,
+            rtc: has_timer.then(Rtc::init),
+        }
+    }
+
+    pub(super) fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    pub(super) fn restore_rtc_clock(&mut self, running: &Mbc3) {
+        if let (Some(rtc), Some(running)) = (self.rtc.as_mut(), running.rtc.as_ref()) {
+            rtc.restore_clock(running);
+        }
+    }
+
+    pub(super) fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    /// Returns the selected register if an RTC register is mapped to 0xA000-0xBFFF
+    fn selected_rtc_register(&self) -> Option<RtcRegister> {
+        self.rtc.as_ref()?;
+
+        RtcRegister::from_select(self.ram_bank_select)
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank),
+        };
+
+        self.memory.read_rom(bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, byte: u8) {
+        match address {
+            0..=RAM_ENABLE_END => {
+                self.ram_enabled = (byte & 0x0F) == RAM_ENABLE_VALUE;
+            }
+            ROM_BANK_NUMBER_START..=ROM_BANK_NUMBER_END => {
+                let bank_number = byte & 0x7F;
+
+                // bank 0 is not valid
+                self.rom_bank = if bank_number == 0 { 1 } else { bank_number };
+            }
+            RAM_BANK_NUMBER_START..=RAM_BANK_NUMBER_END => {
+                self.ram_bank_select = byte & 0x0F;
+            }
+            LATCH_CLOCK_DATA_START..=LATCH_CLOCK_DATA_END => {
+                // the RTC footer of the battery save changes with the latched registers
+                if let Some(rtc) = self.rtc.as_mut() {
+                    if rtc.write_latch(byte) {
+                        self.memory.ram_dirty = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return BYTE_INVALID_READ;
+        }
+
+        match (self.selected_rtc_register(), self.rtc.as_ref()) {
+            (Some(register), Some(rtc)) => rtc.read(register),
+            _ if self.ram_bank_select <= 0x03 => self
+                .memory
+                .read_ram(usize::from(self.ram_bank_select), address),
+            _ => BYTE_INVALID_READ,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+
+        if let Some(register) = self.selected_rtc_register() {
+            if let Some(rtc) = self.rtc.as_mut() {
+                rtc.write(register, byte);
+                self.memory.ram_dirty = true;
+            }
+        } else if self.ram_bank_select <= 0x03 {
+            self.memory
+                .write_ram(usize::from(self.ram_bank_select), address, byte);
+        }
+    }
+
+    fn step(&mut self, t_cycles: u8) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.step(t_cycles);
+        }
+    }
+}
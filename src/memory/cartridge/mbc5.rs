@@ -0,0 +1,110 @@
+use super::{CartridgeEvent, CartridgeMemory, Mapper};
+use crate::memory::bus::BYTE_INVALID_READ;
+use serde::{Deserialize, Serialize};
+
+const RAM_ENABLE_END: u16 = 0x1FFF;
+const RAM_ENABLE_VALUE: u8 = 0x0A;
+const ROM_BANK_LOW_START: u16 = 0x2000;
+const ROM_BANK_LOW_END: u16 = 0x2FFF;
+const ROM_BANK_HIGH_START: u16 = 0x3000;
+const ROM_BANK_HIGH_END: u16 = 0x3FFF;
+const RAM_BANK_NUMBER_START: u16 = 0x4000;
+const RAM_BANK_NUMBER_END: u16 = 0x5FFF;
+/// Bit 3 of the RAM bank number drives the motor on rumble cartridges
+const RUMBLE_MOTOR_BIT: u8 = 0x08;
+
+/// MBC5 with optional rumble motor, see https://gbdev.io/pandocs/MBC5.html
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc5 {
+    pub(super) memory: CartridgeMemory,
+    ram_enabled: bool,
+    /// 9-bit ROM bank number mapped to 0x4000-0x7FFF, unlike older MBCs bank 0 is valid
+    rom_bank: u16,
+    /// 4-bit RAM bank number, rumble cartridges only use the lower 3 bits
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble_active: bool,
+    #[serde(skip)]
+    events: Vec<CartridgeEvent>,
+}
+
+impl Mbc5 {
+    pub(super) fn init(memory: CartridgeMemory, has_rumble: bool) -> Self {
+        if has_rumble {
+            log::info!("Detected MBC5 rumble cartridge");
+        }
+
+        Self {
+            memory,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble_active: false,
+            events: Vec::new(),
+        }
+    }
+
+    fn write_ram_bank(&mut self, byte: u8) {
+        if !self.has_rumble {
+            self.ram_bank = byte & 0x0F;
+            return;
+        }
+
+        self.ram_bank = byte & 0x07;
+
+        let rumble_active = byte & RUMBLE_MOTOR_BIT != 0;
+
+        // only report changes, games keep rewriting the bank number while the motor runs
+        if rumble_active != self.rumble_active {
+            self.rumble_active = rumble_active;
+            self.events.push(CartridgeEvent::Rumble(rumble_active));
+        }
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3FFF => 0,
+            _ => usize::from(self.rom_bank),
+        };
+
+        self.memory.read_rom(bank, address)
+    }
+
+    fn write_rom(&mut self, address: u16, byte: u8) {
+        match address {
+            0..=RAM_ENABLE_END => {
+                self.ram_enabled = byte == RAM_ENABLE_VALUE;
+            }
+            ROM_BANK_LOW_START..=ROM_BANK_LOW_END => {
+                self.rom_bank = (self.rom_bank & 0x100) | u16::from(byte);
+            }
+            ROM_BANK_HIGH_START..=ROM_BANK_HIGH_END => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (u16::from(byte & 0x01) << 8);
+            }
+            RAM_BANK_NUMBER_START..=RAM_BANK_NUMBER_END => self.write_ram_bank(byte),
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return BYTE_INVALID_READ;
+        }
+
+        self.memory.read_ram(usize::from(self.ram_bank), address)
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        if self.ram_enabled {
+            self.memory
+                .write_ram(usize::from(self.ram_bank), address, byte);
+        }
+    }
+
+    fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
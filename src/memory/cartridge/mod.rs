@@ -0,0 +1,353 @@
+mod header;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod rom_only;
+mod rtc;
+
+use super::bus::{BYTE_INVALID_READ, CARTRIDGE_RAM_SIZE, CARTRIDGE_ROM_SIZE, CARTRIDGE_TYPE};
+use anyhow::{bail, Result};
+use header::{RamSize, RomSize, RAM_BANK_SIZE, ROM_BANK_SIZE};
+use mbc1::Mbc1;
+use mbc2::{Mbc2, MBC2_RAM_SIZE};
+use mbc3::Mbc3;
+use mbc5::Mbc5;
+use rom_only::RomOnly;
+use serde::{Deserialize, Serialize};
+
+pub use header::{CartridgeType, MapperKind};
+pub use rtc::RtcClock;
+
+/// Memory mapped cartridge interface. ROM addresses are in 0x0000-0x7FFF, RAM addresses are
+/// relative to the start of external RAM at 0xA000.
+pub(crate) trait Mapper {
+    fn read_rom(&self, address: u16) -> u8;
+    /// Triggers state changes when specific ROM addresses are written to. Never writes into
+    /// actual ROM sections!
+    fn write_rom(&mut self, address: u16, byte: u8);
+    fn read_ram(&self, address: u16) -> u8;
+    fn write_ram(&mut self, address: u16, byte: u8);
+    /// Advances time based components like the MBC3 real-time clock
+    fn step(&mut self, _t_cycles: u8) {}
+    /// Returns all events emitted by the cartridge hardware since the last call
+    fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+        Vec::new()
+    }
+}
+
+/// Cartridge hardware that has no representation inside the emulated system
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CartridgeEvent {
+    /// The rumble motor of an MBC5 rumble cartridge was turned on or off
+    Rumble(bool),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Cartridge {
+    RomOnly(RomOnly),
+    Mbc1(Mbc1),
+    Mbc2(Mbc2),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+}
+
+impl Cartridge {
+    /// Selects the mapper based on the cartridge type byte in the header
+    pub(super) fn from_contents(cartridge_contents: &[u8]) -> Result<Self> {
+        if cartridge_contents.len() <= usize::from(CARTRIDGE_RAM_SIZE) {
+            bail!(
+                "Cartridge of size 0x{:02X}B is too small to contain a header",
+                cartridge_contents.len()
+            );
+        }
+
+        let cartridge_type =
+            CartridgeType::from_header(cartridge_contents[usize::from(CARTRIDGE_TYPE)])?;
+        let ram_bytes = match cartridge_type.mapper {
+            MapperKind::Mbc2 => MBC2_RAM_SIZE,
+            _ if cartridge_type.has_ram => {
+                RamSize::from_header(cartridge_contents[usize::from(CARTRIDGE_RAM_SIZE)])?.bytes()
+            }
+            // RAM is only allocated if the cartridge type includes RAM, regardless of the RAM
+            // size in the header
+            _ => 0,
+        };
+        let memory =
+            CartridgeMemory::init(cartridge_contents, ram_bytes, cartridge_type.has_battery)?;
+
+        log::info!("Cartridge type: {:?}", cartridge_type);
+
+        let cartridge = match cartridge_type.mapper {
+            MapperKind::RomOnly => Self::RomOnly(RomOnly::init(memory)),
+            MapperKind::Mbc1 => Self::Mbc1(Mbc1::init(memory)),
+            MapperKind::Mbc2 => Self::Mbc2(Mbc2::init(memory)),
+            MapperKind::Mbc3 => Self::Mbc3(Mbc3::init(memory, cartridge_type.has_timer)),
+            MapperKind::Mbc5 => Self::Mbc5(Mbc5::init(memory, cartridge_type.has_rumble)),
+        };
+
+        Ok(cartridge)
+    }
+
+    pub(crate) fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Self::Mbc3(mapper) = self {
+            mapper.set_rtc_clock(clock);
+        }
+    }
+
+    fn memory(&self) -> &CartridgeMemory {
+        match self {
+            Self::RomOnly(mapper) => &mapper.memory,
+            Self::Mbc1(mapper) => &mapper.memory,
+            Self::Mbc2(mapper) => &mapper.memory,
+            Self::Mbc3(mapper) => &mapper.memory,
+            Self::Mbc5(mapper) => &mapper.memory,
+        }
+    }
+
+    fn memory_mut(&mut self) -> &mut CartridgeMemory {
+        match self {
+            Self::RomOnly(mapper) => &mut mapper.memory,
+            Self::Mbc1(mapper) => &mut mapper.memory,
+            Self::Mbc2(mapper) => &mut mapper.memory,
+            Self::Mbc3(mapper) => &mut mapper.memory,
+            Self::Mbc5(mapper) => &mut mapper.memory,
+        }
+    }
+
+    pub(crate) fn rom_checksum(&self) -> u32 {
+        self.memory().rom_checksum
+    }
+
+    /// Copies the ROM, which is not part of save states, from the running cartridge
+    pub(crate) fn restore_rom(&mut self, running: &Cartridge) {
+        let memory = self.memory_mut();
+
+        memory.rom = running.memory().rom.clone();
+        memory.rom_checksum = running.memory().rom_checksum;
+    }
+
+    /// Copies the RTC clock mode, which is not part of save states, from the running cartridge
+    pub(crate) fn restore_rtc_clock(&mut self, running: &Cartridge) {
+        if let (Self::Mbc3(mapper), Self::Mbc3(running)) = (self, running) {
+            mapper.restore_rtc_clock(running);
+        }
+    }
+
+    /// Only cartridges with a battery keep their RAM and clock contents when powered off
+    pub(crate) fn has_battery(&self) -> bool {
+        self.memory().battery
+    }
+
+    /// Whether the RAM or RTC was written to since the last battery save
+    pub(crate) fn is_ram_dirty(&self) -> bool {
+        self.memory().ram_dirty
+    }
+
+    /// Serializes the battery backed RAM in the `.sav` format used by other emulators: the raw
+    /// RAM contents, followed by the RTC footer for MBC3 cartridges with a timer
+    pub(crate) fn battery_save(&mut self) -> Vec<u8> {
+        let memory = self.memory_mut();
+        memory.ram_dirty = false;
+
+        let mut contents = memory.ram.clone();
+
+        if let Self::Mbc3(mapper) = self {
+            if let Some(rtc) = mapper.rtc_mut() {
+                contents.extend(rtc.save_footer());
+            }
+        }
+
+        contents
+    }
+
+    pub(crate) fn load_battery_save(&mut self, contents: &[u8]) -> Result<()> {
+        let memory = self.memory_mut();
+        let ram_size = memory.ram.len();
+
+        // truncated saves or saves of emulators using a smaller RAM size are loaded partially
+        if contents.len() < ram_size {
+            log::warn!(
+                "Save file of size 0x{:02X}B is too small for 0x{:02X}B of cartridge RAM, filling the rest with zeros",
+                contents.len(),
+                ram_size
+            );
+        }
+
+        let length = ram_size.min(contents.len());
+        memory.ram[..length].copy_from_slice(&contents[..length]);
+        memory.ram[length..].fill(0);
+        memory.ram_dirty = false;
+
+        let footer = &contents[length..];
+
+        match self {
+            Self::Mbc3(mapper) if !footer.is_empty() => match mapper.rtc_mut() {
+                Some(rtc) => rtc.load_footer(footer)?,
+                None => log::warn!("Ignoring RTC footer for cartridge without timer"),
+            },
+            _ if !footer.is_empty() => {
+                log::warn!("Ignoring 0x{:02X}B of trailing save data", footer.len())
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Mapper for Cartridge {
+    fn read_rom(&self, address: u16) -> u8 {
+        match self {
+            Self::RomOnly(mapper) => mapper.read_rom(address),
+            Self::Mbc1(mapper) => mapper.read_rom(address),
+            Self::Mbc2(mapper) => mapper.read_rom(address),
+            Self::Mbc3(mapper) => mapper.read_rom(address),
+            Self::Mbc5(mapper) => mapper.read_rom(address),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, byte: u8) {
+        match self {
+            Self::RomOnly(mapper) => mapper.write_rom(address, byte),
+            Self::Mbc1(mapper) => mapper.write_rom(address, byte),
+            Self::Mbc2(mapper) => mapper.write_rom(address, byte),
+            Self::Mbc3(mapper) => mapper.write_rom(address, byte),
+            Self::Mbc5(mapper) => mapper.write_rom(address, byte),
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self {
+            Self::RomOnly(mapper) => mapper.read_ram(address),
+            Self::Mbc1(mapper) => mapper.read_ram(address),
+            Self::Mbc2(mapper) => mapper.read_ram(address),
+            Self::Mbc3(mapper) => mapper.read_ram(address),
+            Self::Mbc5(mapper) => mapper.read_ram(address),
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        match self {
+            Self::RomOnly(mapper) => mapper.write_ram(address, byte),
+            Self::Mbc1(mapper) => mapper.write_ram(address, byte),
+            Self::Mbc2(mapper) => mapper.write_ram(address, byte),
+            Self::Mbc3(mapper) => mapper.write_ram(address, byte),
+            Self::Mbc5(mapper) => mapper.write_ram(address, byte),
+        }
+    }
+
+    fn step(&mut self, t_cycles: u8) {
+        match self {
+            Self::RomOnly(mapper) => mapper.step(t_cycles),
+            Self::Mbc1(mapper) => mapper.step(t_cycles),
+            Self::Mbc2(mapper) => mapper.step(t_cycles),
+            Self::Mbc3(mapper) => mapper.step(t_cycles),
+            Self::Mbc5(mapper) => mapper.step(t_cycles),
+        }
+    }
+
+    fn drain_events(&mut self) -> Vec<CartridgeEvent> {
+        match self {
+            Self::RomOnly(mapper) => mapper.drain_events(),
+            Self::Mbc1(mapper) => mapper.drain_events(),
+            Self::Mbc2(mapper) => mapper.drain_events(),
+            Self::Mbc3(mapper) => mapper.drain_events(),
+            Self::Mbc5(mapper) => mapper.drain_events(),
+        }
+    }
+}
+
+/// ROM and external RAM contents shared by all mappers
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct CartridgeMemory {
+    /// Not part of save states, restored from the running cartridge instead
+    #[serde(skip)]
+    rom: Vec<u8>,
+    /// CRC32 of the cartridge file, identifies the ROM in save states
+    #[serde(skip)]
+    rom_checksum: u32,
+    rom_size: RomSize,
+    ram: Vec<u8>,
+    battery: bool,
+    /// Set on RAM writes and RTC writes or latches, cleared when the battery save is written
+    ram_dirty: bool,
+}
+
+// load the cartridge contents into rom. Needed since Mapper::write_rom ignores writes into the ROM
+// section
+fn write_cartridge_to_rom(rom: &mut [u8], cartridge_contents: &[u8]) {
+    let rom_size = rom.len();
+
+    if cartridge_contents.len() != rom_size {
+        log::warn!(
+            "Requested ROM size of 0x{:02X}B, but got cartridge of size 0x{:02X}B instead",
+            rom_size,
+            cartridge_contents.len()
+        );
+    }
+
+    let length = rom_size.min(cartridge_contents.len());
+    rom[..length].copy_from_slice(&cartridge_contents[..length]);
+}
+
+impl CartridgeMemory {
+    /// Allocate a single Vec for the cartridge rom and ram
+    fn init(cartridge_contents: &[u8], ram_bytes: usize, battery: bool) -> Result<Self> {
+        let rom_size = RomSize::from_header(cartridge_contents[usize::from(CARTRIDGE_ROM_SIZE)])?;
+
+        let mut rom = vec![BYTE_INVALID_READ; rom_size.bytes()];
+
+        write_cartridge_to_rom(&mut rom, cartridge_contents);
+
+        Ok(Self {
+            rom,
+            rom_checksum: crc32fast::hash(cartridge_contents),
+            rom_size,
+            ram: vec![BYTE_INVALID_READ; ram_bytes],
+            battery,
+            ram_dirty: false,
+        })
+    }
+
+    fn rom_banks(&self) -> usize {
+        self.rom_size.banks()
+    }
+
+    /// Reads from the given 16 KiB ROM bank, banks above the available ROM size wrap around
+    fn read_rom(&self, bank: usize, address: u16) -> u8 {
+        let bank = bank % self.rom_banks();
+
+        self.rom[bank * ROM_BANK_SIZE + usize::from(address) % ROM_BANK_SIZE]
+    }
+
+    fn has_ram(&self) -> bool {
+        !self.ram.is_empty()
+    }
+
+    /// Index into the given 8 KiB RAM bank, banks above the available RAM size wrap around
+    fn ram_index(&self, bank: usize, address: u16) -> usize {
+        (bank * RAM_BANK_SIZE + usize::from(address)) % self.ram.len()
+    }
+
+    fn read_ram(&self, bank: usize, address: u16) -> u8 {
+        if !self.has_ram() {
+            return BYTE_INVALID_READ;
+        }
+
+        self.ram[self.ram_index(bank, address)]
+    }
+
+    fn write_ram(&mut self, bank: usize, address: u16, byte: u8) {
+        if !self.has_ram() {
+            return;
+        }
+
+        let index = self.ram_index(bank, address);
+
+        if self.ram[index] != byte {
+            self.ram[index] = byte;
+            self.ram_dirty = true;
+        }
+    }
+}
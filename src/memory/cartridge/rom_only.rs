@@ -0,0 +1,32 @@
+use super::{CartridgeMemory, Mapper, ROM_BANK_SIZE};
+use serde::{Deserialize, Serialize};
+
+/// Cartridges without a memory bank controller, 32 KiB ROM and up to 8 KiB of optional RAM
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RomOnly {
+    pub(super) memory: CartridgeMemory,
+}
+
+impl RomOnly {
+    pub(super) fn init(memory: CartridgeMemory) -> Self {
+        Self { memory }
+    }
+}
+
+impl Mapper for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = usize::from(address) / ROM_BANK_SIZE;
+
+        self.memory.read_rom(bank, address)
+    }
+
+    fn write_rom(&mut self, _address: u16, _byte: u8) {}
+
+    fn read_ram(&self, address: u16) -> u8 {
+        self.memory.read_ram(0, address)
+    }
+
+    fn write_ram(&mut self, address: u16, byte: u8) {
+        self.memory.write_ram(0, address, byte)
+    }
+}
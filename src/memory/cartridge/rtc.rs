@@ -0,0 +1,289 @@
+use crate::emulator::CLOCK_SPEED;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_HOUR: u64 = 60 * SECONDS_PER_MINUTE;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+/// The day counter is 9 bits wide, overflowing it sets the day carry bit
+const DAY_COUNTER_LIMIT: u64 = 512;
+
+const DAY_HIGH_BIT_DAY: u8 = 0x01;
+const DAY_HIGH_BIT_HALT: u8 = 0x40;
+const DAY_HIGH_BIT_CARRY: u8 = 0x80;
+
+/// Size of the RTC footer appended to the save RAM by other emulators (BGB, VBA-M, SameBoy):
+/// current and latched registers as 32-bit little endian values, followed by a 64-bit UNIX
+/// timestamp. Older files use a 32-bit timestamp instead.
+const RTC_FOOTER_SIZE: usize = 48;
+const RTC_FOOTER_SIZE_LEGACY: usize = 44;
+const RTC_FOOTER_REGISTERS_SIZE: usize = 20;
+
+/// Time source of the real-time clock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RtcClock {
+    /// Advance one second every 4194304 emulated t-cycles, pauses with the emulation
+    #[default]
+    Emulated,
+    /// Advance according to the host wall-clock time
+    WallClock,
+}
+
+/// Register selected by writing 0x08-0x0C to 0x4000-0x5FFF
+#[derive(Clone, Copy, Debug)]
+pub(super) enum RtcRegister {
+    Seconds,
+    Minutes,
+    Hours,
+    DayLow,
+    DayHigh,
+}
+
+impl RtcRegister {
+    pub(super) fn from_select(value: u8) -> Option<Self> {
+        match value {
+            0x08 => Some(Self::Seconds),
+            0x09 => Some(Self::Minutes),
+            0x0A => Some(Self::Hours),
+            0x0B => Some(Self::DayLow),
+            0x0C => Some(Self::DayHigh),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct RtcRegisters {
+    pub(super) seconds: u8,
+    pub(super) minutes: u8,
+    pub(super) hours: u8,
+    pub(super) day_low: u8,
+    /// Bit 0: upper bit of the day counter, bit 6: halt, bit 7: day counter carry
+    pub(super) day_high: u8,
+}
+
+impl RtcRegisters {
+    fn read(&self, register: RtcRegister) -> u8 {
+        match register {
+            RtcRegister::Seconds => self.seconds & 0x3F,
+            RtcRegister::Minutes => self.minutes & 0x3F,
+            RtcRegister::Hours => self.hours & 0x1F,
+            RtcRegister::DayLow => self.day_low,
+            RtcRegister::DayHigh => self.day_high & 0xC1,
+        }
+    }
+
+    fn to_footer(self) -> [u8; RTC_FOOTER_REGISTERS_SIZE] {
+        let mut footer = [0; RTC_FOOTER_REGISTERS_SIZE];
+        let registers = [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.day_low,
+            self.day_high,
+        ];
+
+        for (chunk, register) in footer.chunks_exact_mut(4).zip(registers) {
+            chunk.copy_from_slice(&u32::from(register).to_le_bytes());
+        }
+
+        footer
+    }
+
+    fn from_footer(footer: &[u8]) -> Self {
+        let register = |index: usize| footer[index * 4];
+
+        Self {
+            seconds: register(0) & 0x3F,
+            minutes: register(1) & 0x3F,
+            hours: register(2) & 0x1F,
+            day_low: register(3),
+            day_high: register(4) & 0xC1,
+        }
+    }
+
+    fn halted(&self) -> bool {
+        self.day_high & DAY_HIGH_BIT_HALT != 0
+    }
+
+    fn days(&self) -> u64 {
+        u64::from(self.day_low) | (u64::from(self.day_high & DAY_HIGH_BIT_DAY) << 8)
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let total = u64::from(self.seconds)
+            + u64::from(self.minutes) * SECONDS_PER_MINUTE
+            + u64::from(self.hours) * SECONDS_PER_HOUR
+            + self.days() * SECONDS_PER_DAY
+            + seconds;
+
+        let days = total / SECONDS_PER_DAY;
+
+        self.seconds = (total % SECONDS_PER_MINUTE) as u8;
+        self.minutes = (total % SECONDS_PER_HOUR / SECONDS_PER_MINUTE) as u8;
+        self.hours = (total % SECONDS_PER_DAY / SECONDS_PER_HOUR) as u8;
+        self.day_low = days as u8;
+        self.day_high =
+            (self.day_high & !DAY_HIGH_BIT_DAY) | ((days >> 8) as u8 & DAY_HIGH_BIT_DAY);
+
+        // the carry bit stays set until it is cleared by the game
+        if days >= DAY_COUNTER_LIMIT {
+            self.day_high |= DAY_HIGH_BIT_CARRY;
+        }
+    }
+}
+
+/// MBC3 real-time clock, see https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct Rtc {
+    /// Not part of save states, restored from the running clock instead
+    #[serde(skip)]
+    clock: RtcClock,
+    pub(super) registers: RtcRegisters,
+    pub(super) latched: RtcRegisters,
+    /// Last value written to the latch register, latching happens on a 0x00 -> 0x01 write
+    last_latch_write: u8,
+    /// Emulated clock: t-cycles passed since the last second
+    cycles: u32,
+    /// Wall clock: host time of the last update of the registers, not part of save states
+    #[serde(skip, default = "SystemTime::now")]
+    last_sync: SystemTime,
+}
+
+impl Rtc {
+    pub(super) fn init() -> Self {
+        Self {
+            clock: RtcClock::default(),
+            registers: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            last_latch_write: 0xFF,
+            cycles: 0,
+            last_sync: SystemTime::now(),
+        }
+    }
+
+    pub(super) fn set_clock(&mut self, clock: RtcClock) {
+        self.sync();
+        self.clock = clock;
+        self.cycles = 0;
+        self.last_sync = SystemTime::now();
+    }
+
+    /// Keeps the clock mode of the running clock when loading a save state. The wall clock
+    /// continues from the time of loading.
+    pub(super) fn restore_clock(&mut self, running: &Rtc) {
+        self.clock = running.clock;
+        self.last_sync = SystemTime::now();
+    }
+
+    pub(super) fn step(&mut self, t_cycles: u8) {
+        if self.clock != RtcClock::Emulated || self.registers.halted() {
+            return;
+        }
+
+        self.cycles += u32::from(t_cycles);
+
+        if self.cycles >= CLOCK_SPEED {
+            self.cycles -= CLOCK_SPEED;
+            self.registers.advance(1);
+        }
+    }
+
+    /// Catches up with the host time when driven by the wall clock
+    fn sync(&mut self) {
+        if self.clock != RtcClock::WallClock {
+            return;
+        }
+
+        let now = SystemTime::now();
+        let elapsed = now.duration_since(self.last_sync).unwrap_or_default();
+
+        if self.registers.halted() {
+            self.last_sync = now;
+            return;
+        }
+
+        self.registers.advance(elapsed.as_secs());
+        // keep the fractional second for the next sync
+        self.last_sync = now - (elapsed - Duration::from_secs(elapsed.as_secs()));
+    }
+
+    /// Returns whether the registers were latched
+    pub(super) fn write_latch(&mut self, byte: u8) -> bool {
+        let latch = self.last_latch_write == 0x00 && byte == 0x01;
+
+        if latch {
+            self.sync();
+            self.latched = self.registers;
+        }
+
+        self.last_latch_write = byte;
+
+        latch
+    }
+
+    pub(super) fn read(&self, register: RtcRegister) -> u8 {
+        self.latched.read(register)
+    }
+
+    /// Serializes the clock in the common save file footer format
+    pub(super) fn save_footer(&mut self) -> Vec<u8> {
+        self.sync();
+
+        let timestamp = match self.clock {
+            RtcClock::Emulated => SystemTime::now(),
+            RtcClock::WallClock => self.last_sync,
+        };
+        let timestamp = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+        footer.extend_from_slice(&self.registers.to_footer());
+        footer.extend_from_slice(&self.latched.to_footer());
+        footer.extend_from_slice(&timestamp.to_le_bytes());
+
+        footer
+    }
+
+    /// Restores the clock from a save file footer. The wall clock catches up with the time
+    /// passed since the footer was written, the emulated clock continues where it stopped.
+    pub(super) fn load_footer(&mut self, footer: &[u8]) -> Result<()> {
+        let timestamp = match footer.len() {
+            RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into()?),
+            RTC_FOOTER_SIZE_LEGACY => u64::from(u32::from_le_bytes(footer[40..44].try_into()?)),
+            length => bail!("Invalid RTC save footer of size {length}B"),
+        };
+
+        self.registers = RtcRegisters::from_footer(&footer[..RTC_FOOTER_REGISTERS_SIZE]);
+        self.latched = RtcRegisters::from_footer(&footer[RTC_FOOTER_REGISTERS_SIZE..]);
+        self.cycles = 0;
+        self.last_sync = UNIX_EPOCH + Duration::from_secs(timestamp);
+
+        if self.clock == RtcClock::WallClock {
+            self.sync();
+        } else {
+            self.last_sync = SystemTime::now();
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn write(&mut self, register: RtcRegister, byte: u8) {
+        self.sync();
+
+        match register {
+            RtcRegister::Seconds => {
+                self.registers.seconds = byte & 0x3F;
+                // writing the seconds resets the sub-second counter
+                self.cycles = 0;
+            }
+            RtcRegister::Minutes => self.registers.minutes = byte & 0x3F,
+            RtcRegister::Hours => self.registers.hours = byte & 0x1F,
+            RtcRegister::DayLow => self.registers.day_low = byte,
+            RtcRegister::DayHigh => self.registers.day_high = byte & 0xC1,
+        }
+    }
+}
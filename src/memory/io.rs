@@ -1,6 +1,18 @@
 use super::bus::Bus;
 
 impl Bus {
+    pub fn get_timer_divider(&self) -> u8 {
+        (self.io.system_counter >> 8) as u8
+    }
+
+    pub fn set_timer_divider(&mut self, value: u8) {
+        self.io.system_counter = u16::from(value) << 8;
+    }
+
+    pub fn tick_system_counter(&mut self, t_cycles: u8) {
+        self.io.system_counter = self.io.system_counter.wrapping_add(t_cycles.into());
+    }
+
     pub fn get_lcd_control(&self) -> u8 {
         self.io.lcd_control
     }
@@ -17,6 +29,14 @@ impl Bus {
         self.io.lcd_stat = value;
     }
 
+    pub fn get_stat_line(&self) -> bool {
+        self.io.stat_line
+    }
+
+    pub fn set_stat_line(&mut self, value: bool) {
+        self.io.stat_line = value;
+    }
+
     pub fn get_lcd_y(&self) -> u8 {
         self.io.lcd_y
     }
@@ -29,6 +49,18 @@ impl Bus {
         self.io.lcd_y_compare
     }
 
+    pub fn get_bg_palette(&self) -> u8 {
+        self.io.bg_palette
+    }
+
+    pub fn get_obj_palette_0(&self) -> u8 {
+        self.io.obj_palette_0
+    }
+
+    pub fn get_obj_palette_1(&self) -> u8 {
+        self.io.obj_palette_1
+    }
+
     pub fn get_window_y(&self) -> u8 {
         self.io.window_y
     }
@@ -0,0 +1,207 @@
+use super::bus::{get_bit_status, Bus};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const JOYP_BIT_SELECT_BUTTONS: u8 = 5;
+const JOYP_BIT_SELECT_DPAD: u8 = 4;
+const JOYP_SELECT_MASK: u8 = 0x30;
+const JOYP_UNUSED_BITS: u8 = 0xC0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Self::Right,
+        Self::Left,
+        Self::Up,
+        Self::Down,
+        Self::A,
+        Self::B,
+        Self::Select,
+        Self::Start,
+    ];
+
+    /// Bit of the button inside the lower nibble of JOYP, shared between both select lines
+    fn line_bit(&self) -> u8 {
+        match self {
+            Self::Right | Self::A => 0b0001,
+            Self::Left | Self::B => 0b0010,
+            Self::Up | Self::Select => 0b0100,
+            Self::Down | Self::Start => 0b1000,
+        }
+    }
+
+    fn is_dpad(&self) -> bool {
+        matches!(self, Self::Right | Self::Left | Self::Up | Self::Down)
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Right => "RIGHT",
+            Self::Left => "LEFT",
+            Self::Up => "UP",
+            Self::Down => "DOWN",
+            Self::A => "A",
+            Self::B => "B",
+            Self::Select => "SELECT",
+            Self::Start => "START",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|button| button.to_string().eq_ignore_ascii_case(value))
+            .ok_or_else(|| format!("Unknown button '{value}'"))
+    }
+}
+
+/// Key state changes sent from the window thread to the emulation thread
+#[derive(Clone, Copy, Debug)]
+pub enum JoypadEvent {
+    Pressed(Button),
+    Released(Button),
+}
+
+/// Button state and select lines of the JOYP register, see https://gbdev.io/pandocs/Joypad_Input.html
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Joypad {
+    /// P15 (bit 5) and P14 (bit 4), a line is selected when its bit is 0
+    select: u8,
+    /// Pressed action buttons, set bits mean pressed
+    buttons: u8,
+    /// Pressed directional buttons, set bits mean pressed
+    dpad: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self {
+            select: JOYP_SELECT_MASK,
+            buttons: 0,
+            dpad: 0,
+        }
+    }
+}
+
+impl Joypad {
+    pub(super) fn read(&self) -> u8 {
+        JOYP_UNUSED_BITS | self.select | self.input_lines()
+    }
+
+    pub(super) fn write(&mut self, byte: u8) {
+        self.select = byte & JOYP_SELECT_MASK;
+    }
+
+    /// Lower nibble of JOYP. Lines are pulled low by pressed buttons of any selected group.
+    fn input_lines(&self) -> u8 {
+        let mut pressed = 0;
+
+        if !get_bit_status(self.select, JOYP_BIT_SELECT_BUTTONS) {
+            pressed |= self.buttons;
+        }
+
+        if !get_bit_status(self.select, JOYP_BIT_SELECT_DPAD) {
+            pressed |= self.dpad;
+        }
+
+        !pressed & 0x0F
+    }
+
+    fn is_pressed(&self, button: Button) -> bool {
+        let group = if button.is_dpad() {
+            self.dpad
+        } else {
+            self.buttons
+        };
+
+        group & button.line_bit() != 0
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        let group = if button.is_dpad() {
+            &mut self.dpad
+        } else {
+            &mut self.buttons
+        };
+
+        if pressed {
+            *group |= button.line_bit();
+        } else {
+            *group &= !button.line_bit();
+        }
+    }
+}
+
+impl Bus {
+    pub fn handle_joypad_event(&mut self, event: JoypadEvent) {
+        let previous_lines = self.joypad.input_lines();
+
+        match event {
+            JoypadEvent::Pressed(button) => self.joypad.set_button(button, true),
+            JoypadEvent::Released(button) => self.joypad.set_button(button, false),
+        }
+
+        self.check_joypad_interrupt(previous_lines);
+    }
+
+    pub(super) fn write_joypad(&mut self, byte: u8) {
+        let previous_lines = self.joypad.input_lines();
+
+        self.joypad.write(byte);
+
+        self.check_joypad_interrupt(previous_lines);
+    }
+
+    /// Presses exactly the given buttons and releases all others
+    pub fn set_buttons(&mut self, pressed: &[Button]) {
+        for button in Button::ALL {
+            let event = if pressed.contains(&button) {
+                JoypadEvent::Pressed(button)
+            } else {
+                JoypadEvent::Released(button)
+            };
+
+            self.handle_joypad_event(event);
+        }
+    }
+
+    pub fn pressed_buttons(&self) -> Vec<Button> {
+        Button::ALL
+            .into_iter()
+            .filter(|button| self.joypad.is_pressed(*button))
+            .collect()
+    }
+
+    /// Whether a pressed button of a selected group pulls one of the input lines low, which also
+    /// ends STOP mode
+    pub fn joypad_line_low(&self) -> bool {
+        self.joypad.input_lines() != 0x0F
+    }
+
+    /// The joypad interrupt is requested whenever one of the input lines goes from high to low
+    fn check_joypad_interrupt(&mut self, previous_lines: u8) {
+        if previous_lines & !self.joypad.input_lines() != 0 {
+            self.request_joypad_interrupt();
+        }
+    }
+}
@@ -1,3 +1,7 @@
 pub mod bus;
+pub mod cartridge;
 mod io;
+pub mod joypad;
 mod mem;
+pub mod serial;
+mod timer;
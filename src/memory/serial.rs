@@ -0,0 +1,221 @@
+#![allow(unused)]
+use std::sync::{Arc, Mutex};
+
+use super::bus::Bus;
+
+const SC_TRANSFER_ENABLE: u8 = 0b10000000;
+const SC_INTERNAL_CLOCK: u8 = 0b1;
+/// SC bits 1-6 are unused on DMG and always read as set
+const SC_UNUSED_BITS: u8 = 0b01111110;
+/// Byte shifted in when nothing drives the serial input line
+const SERIAL_IDLE_BYTE: u8 = 0xFF;
+
+/// The other end of the link cable. Transfers are exchanged a byte at a time, the bit timing is
+/// done by the serial port of the bus.
+pub trait SerialDevice: Send {
+    /// Internal clock: this Game Boy has shifted out `byte` and receives the byte of the connected
+    /// side
+    fn exchange(&mut self, byte: u8) -> u8;
+
+    /// External clock: polled while a transfer is waiting for the connected side to provide the
+    /// clock, with the current contents of SB. Returns the received byte once the connected side
+    /// has clocked the transfer.
+    fn poll_external(&mut self, byte: u8) -> Option<u8>;
+
+    /// Called with the emulated time that passed, lets devices synchronize with the other side
+    fn step(&mut self, _t_cycles: u8) {}
+}
+
+pub type SharedSerialDevice = Arc<Mutex<dyn SerialDevice>>;
+
+pub(super) fn disconnected() -> SharedSerialDevice {
+    Arc::new(Mutex::new(Disconnected))
+}
+
+/// No cable attached, the input line stays high and external clock transfers never finish
+pub struct Disconnected;
+
+impl SerialDevice for Disconnected {
+    fn exchange(&mut self, _byte: u8) -> u8 {
+        SERIAL_IDLE_BYTE
+    }
+
+    fn poll_external(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Collects all bytes sent with the internal clock, which is how test roms report their results.
+/// Clones share the same buffer.
+#[derive(Clone, Default)]
+pub struct CaptureBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl CaptureBuffer {
+    /// Takes the bytes captured since the last call
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+impl SerialDevice for CaptureBuffer {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.0.lock().unwrap().push(byte);
+
+        SERIAL_IDLE_BYTE
+    }
+
+    fn poll_external(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Forwards transfers to a device and captures the sent bytes, which keeps the serial output of
+/// test roms available while another device is connected
+pub(crate) struct SerialTee {
+    device: SharedSerialDevice,
+    capture: CaptureBuffer,
+}
+
+impl SerialTee {
+    pub(crate) fn new(device: SharedSerialDevice, capture: CaptureBuffer) -> Self {
+        Self { device, capture }
+    }
+}
+
+impl SerialDevice for SerialTee {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.capture.0.lock().unwrap().push(byte);
+        self.device.lock().unwrap().exchange(byte)
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        let received = self.device.lock().unwrap().poll_external(byte);
+
+        if received.is_some() {
+            self.capture.0.lock().unwrap().push(byte);
+        }
+
+        received
+    }
+
+    fn step(&mut self, t_cycles: u8) {
+        self.device.lock().unwrap().step(t_cycles);
+    }
+}
+
+#[derive(Default)]
+struct LinkCableState {
+    /// SB of a side waiting for an external clock
+    waiting: [Option<u8>; 2],
+    /// Byte clocked in by the other side, collected by the next poll
+    received: [Option<u8>; 2],
+}
+
+/// One end of a link cable connecting two emulator instances in the same process
+pub struct LinkPort {
+    side: usize,
+    state: Arc<Mutex<LinkCableState>>,
+}
+
+/// Creates both ends of a link cable
+pub fn link_cable() -> (LinkPort, LinkPort) {
+    let state = Arc::new(Mutex::new(LinkCableState::default()));
+
+    (
+        LinkPort {
+            side: 0,
+            state: state.clone(),
+        },
+        LinkPort { side: 1, state },
+    )
+}
+
+impl SerialDevice for LinkPort {
+    /// The other side only receives the byte if it is waiting for an external clock
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut state = self.state.lock().unwrap();
+        let other = 1 - self.side;
+
+        match state.waiting[other].take() {
+            Some(other_byte) => {
+                state.received[other] = Some(byte);
+                other_byte
+            }
+            None => SERIAL_IDLE_BYTE,
+        }
+    }
+
+    fn poll_external(&mut self, byte: u8) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+
+        let received = state.received[self.side].take();
+        state.waiting[self.side] = received.is_none().then_some(byte);
+
+        received
+    }
+}
+
+impl Bus {
+    /// Attaches a device to the serial port, replacing the previous one
+    pub fn connect_serial(&mut self, device: SharedSerialDevice) {
+        self.serial_device = device;
+    }
+
+    pub fn step_serial_device(&mut self, t_cycles: u8) {
+        self.serial_device.lock().unwrap().step(t_cycles);
+    }
+
+    pub(super) fn read_serial_control(&self) -> u8 {
+        self.io.serial_control | SC_UNUSED_BITS
+    }
+
+    /// SC: Setting bit 7 starts a transfer
+    pub(super) fn write_serial_control(&mut self, value: u8) {
+        self.io.serial_control = value & !SC_UNUSED_BITS;
+
+        if value & SC_TRANSFER_ENABLE != 0 {
+            self.io.serial_bits = 0;
+        }
+    }
+
+    fn is_serial_transfer_active(&self, internal_clock: bool) -> bool {
+        let control = self.io.serial_control;
+
+        control & SC_TRANSFER_ENABLE != 0 && (control & SC_INTERNAL_CLOCK != 0) == internal_clock
+    }
+
+    /// Called on every falling edge of bit 8 of the system counter, which clocks the internal
+    /// 8192 Hz serial clock. One bit is shifted per clock, the byte is exchanged after the 8th.
+    pub(super) fn tick_serial_clock(&mut self) {
+        if self.is_serial_transfer_active(true) {
+            self.io.serial_bits += 1;
+
+            if self.io.serial_bits == 8 {
+                let received = self
+                    .serial_device
+                    .lock()
+                    .unwrap()
+                    .exchange(self.io.serial_data);
+
+                self.finish_serial_transfer(received);
+            }
+        } else if self.is_serial_transfer_active(false) {
+            let received = self
+                .serial_device
+                .lock()
+                .unwrap()
+                .poll_external(self.io.serial_data);
+
+            if let Some(received) = received {
+                self.finish_serial_transfer(received);
+            }
+        }
+    }
+
+    fn finish_serial_transfer(&mut self, received: u8) {
+        self.io.serial_data = received;
+        self.io.serial_bits = 0;
+        self.io.serial_control &= !SC_TRANSFER_ENABLE;
+        self.request_serial_interrupt();
+    }
+}
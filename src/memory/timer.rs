@@ -0,0 +1,95 @@
+use super::bus::Bus;
+
+const TAC_ENABLE: u8 = 0b100;
+const TAC_CLOCK_SELECT: u8 = 0b11;
+/// TAC bits 3-7 are unused and always read as set
+const TAC_UNUSED_BITS: u8 = 0b11111000;
+
+/// System counter bit whose falling edge increments TIMA, indexed by the TAC clock select
+const TIMER_COUNTER_BITS: [u8; 4] = [9, 3, 5, 7];
+/// System counter bit whose falling edge clocks the 8192 Hz serial clock
+const SERIAL_COUNTER_BIT: u16 = 1 << 8;
+
+impl Bus {
+    /// TIMA is incremented on the falling edge of the TAC-selected system counter bit, AND'ed with
+    /// the timer enable
+    fn timer_input(&self) -> bool {
+        let control = self.io.timer_control;
+        let bit = TIMER_COUNTER_BITS[usize::from(control & TAC_CLOCK_SELECT)];
+
+        control & TAC_ENABLE != 0 && self.io.system_counter & (1 << bit) != 0
+    }
+
+    /// Advances the system counter one M-cycle at a time
+    pub fn tick_timer(&mut self, t_cycles: u8) {
+        for _ in 0..t_cycles / 4 {
+            self.tick_timer_cycle();
+        }
+    }
+
+    fn tick_timer_cycle(&mut self) {
+        self.io.tima_reloaded = false;
+
+        // TIMA reads 0x00 for one M-cycle after overflowing before TMA is loaded
+        if self.io.tima_overflow {
+            self.io.tima_overflow = false;
+            self.io.tima_reloaded = true;
+            self.io.timer_counter = self.io.timer_modulo;
+            self.request_timer_interrupt();
+        }
+
+        let input = self.timer_input();
+        let serial_clock = self.io.system_counter & SERIAL_COUNTER_BIT != 0;
+
+        self.tick_system_counter(4);
+        self.detect_timer_falling_edge(input);
+
+        if serial_clock && self.io.system_counter & SERIAL_COUNTER_BIT == 0 {
+            self.tick_serial_clock();
+        }
+    }
+
+    fn detect_timer_falling_edge(&mut self, previous_input: bool) {
+        if !previous_input || self.timer_input() {
+            return;
+        }
+
+        let (counter, overflow) = self.io.timer_counter.overflowing_add(1);
+        self.io.timer_counter = counter;
+        self.io.tima_overflow = overflow;
+    }
+
+    /// DIV: Any write resets the whole system counter, which can cause a falling edge
+    pub(super) fn write_timer_divider(&mut self) {
+        let input = self.timer_input();
+        self.io.system_counter = 0;
+        self.detect_timer_falling_edge(input);
+    }
+
+    /// TIMA: Writing during the overflow delay cancels the reload, writing in the cycle TMA is
+    /// loaded has no effect
+    pub(super) fn write_timer_counter(&mut self, value: u8) {
+        if self.io.tima_reloaded {
+            return;
+        }
+
+        self.io.tima_overflow = false;
+        self.io.timer_counter = value;
+    }
+
+    /// TMA: Writing in the cycle TMA is loaded into TIMA also updates TIMA
+    pub(super) fn write_timer_modulo(&mut self, value: u8) {
+        self.io.timer_modulo = value;
+
+        if self.io.tima_reloaded {
+            self.io.timer_counter = value;
+        }
+    }
+
+    /// TAC: Disabling the timer or switching to another counter bit can cause a falling edge
+    pub(super) fn write_timer_control(&mut self, value: u8) {
+        let input = self.timer_input();
+        self.io.timer_control = value | TAC_UNUSED_BITS;
+        self.detect_timer_falling_edge(input);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::emulator::EmulatorState;
+use crate::gameboy::GameBoy;
+use crate::input::{InputPlayer, InputRecorder, InputScript};
+use crate::memory::joypad::{Button, JoypadEvent};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Identifies movie files of this emulator
+const MOVIE_MAGIC: &[u8; 4] = b"GBMV";
+/// Bumped whenever the layout of movie files changes
+const MOVIE_VERSION: u16 = 1;
+const MOVIE_START_POWER_ON: u8 = 0;
+const MOVIE_START_SAVE_STATE: u8 = 1;
+
+/// State a movie starts from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieStart {
+    /// The state the boot rom hands off to the cartridge, without battery save
+    PowerOn,
+    /// A save state created by `GameBoy::save_state`
+    SaveState(Vec<u8>),
+}
+
+/// Recorded inputs, played back deterministically from the same start state of the same ROM.
+///
+/// Files consist of the magic, the format version, the CRC32 of the ROM, the start state, the
+/// length in T-cycles and the input events. Each event is the T-cycle it happens at followed by a
+/// bit mask of the pressed buttons in the order of `Button::ALL`. All numbers are little endian.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_checksum: u32,
+    pub start: MovieStart,
+    /// T-cycles from the start to the end of the recording
+    pub length: u64,
+    pub inputs: InputScript,
+}
+
+impl Movie {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Self::from_bytes(&contents).with_context(|| format!("Failed to load {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut contents = Vec::new();
+
+        contents.extend_from_slice(MOVIE_MAGIC);
+        contents.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        contents.extend_from_slice(&self.rom_checksum.to_le_bytes());
+
+        match &self.start {
+            MovieStart::PowerOn => contents.push(MOVIE_START_POWER_ON),
+            MovieStart::SaveState(state) => {
+                contents.push(MOVIE_START_SAVE_STATE);
+                contents.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                contents.extend_from_slice(state);
+            }
+        }
+
+        contents.extend_from_slice(&self.length.to_le_bytes());
+
+        let events = self.inputs.events();
+        contents.extend_from_slice(&(events.len() as u32).to_le_bytes());
+
+        for event in events {
+            contents.extend_from_slice(&event.cycle.to_le_bytes());
+            contents.push(buttons_to_mask(&event.pressed));
+        }
+
+        contents
+    }
+
+    pub fn from_bytes(contents: &[u8]) -> Result<Self> {
+        let mut reader = ByteReader(contents);
+
+        if reader.take(MOVIE_MAGIC.len())? != MOVIE_MAGIC {
+            bail!("Not a movie file");
+        }
+
+        let version = u16::from_le_bytes(reader.take_array()?);
+
+        if version != MOVIE_VERSION {
+            bail!("Unsupported movie version {version}, expected {MOVIE_VERSION}");
+        }
+
+        let rom_checksum = u32::from_le_bytes(reader.take_array()?);
+
+        let start = match reader.take_array::<1>()?[0] {
+            MOVIE_START_POWER_ON => MovieStart::PowerOn,
+            MOVIE_START_SAVE_STATE => {
+                let length = u32::from_le_bytes(reader.take_array()?);
+                MovieStart::SaveState(reader.take(length as usize)?.to_vec())
+            }
+            start => bail!("Invalid movie start {start}"),
+        };
+
+        let length = u64::from_le_bytes(reader.take_array()?);
+        let event_count = u32::from_le_bytes(reader.take_array()?);
+        let mut inputs = InputScript::default();
+
+        for _ in 0..event_count {
+            let cycle = u64::from_le_bytes(reader.take_array()?);
+            let mask = reader.take_array::<1>()?[0];
+
+            inputs.push(cycle, &mask_to_buttons(mask));
+        }
+
+        Ok(Self {
+            rom_checksum,
+            start,
+            length,
+            inputs,
+        })
+    }
+
+    pub(crate) fn from_recording(
+        rom_checksum: u32,
+        start: MovieStart,
+        recorder: InputRecorder,
+    ) -> Self {
+        Self {
+            rom_checksum,
+            start,
+            length: recorder.cycles(),
+            inputs: recorder.into_script(),
+        }
+    }
+
+    /// Creates an emulator at the start of the movie, which plays the inputs back while stepping
+    pub fn play(&self, cartridge_contents: &[u8]) -> Result<GameBoy> {
+        let mut game_boy = GameBoy::new(cartridge_contents)?;
+        self.restore_start(game_boy.state_mut())?;
+        game_boy.set_input_script(self.inputs.clone());
+
+        Ok(game_boy)
+    }
+
+    fn restore_start(&self, state: &mut EmulatorState) -> Result<()> {
+        let rom_checksum = state.cpu.bus.rom_checksum();
+
+        if self.rom_checksum != rom_checksum {
+            bail!(
+                "Movie belongs to a different ROM (checksum 0x{:08X}, expected 0x{rom_checksum:08X})",
+                self.rom_checksum
+            );
+        }
+
+        if let MovieStart::SaveState(contents) = &self.start {
+            state.load_state(contents)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn buttons_to_mask(pressed: &[Button]) -> u8 {
+    Button::ALL
+        .into_iter()
+        .enumerate()
+        .filter(|(_, button)| pressed.contains(button))
+        .fold(0, |mask, (bit, _)| mask | (1 << bit))
+}
+
+fn mask_to_buttons(mask: u8) -> Vec<Button> {
+    Button::ALL
+        .into_iter()
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, button)| button)
+        .collect()
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        if self.0.len() < length {
+            bail!("Unexpected end of movie file");
+        }
+
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+}
+
+/// What the emulation thread does with a movie
+#[derive(Clone, Debug)]
+pub enum MovieMode {
+    /// Records all inputs until the emulator is closed and writes the movie to the path
+    Record(PathBuf),
+    Play(Movie),
+}
+
+/// Movie being recorded or played back by the emulation thread
+pub(crate) enum MovieSession {
+    Recording {
+        recorder: InputRecorder,
+        rom_checksum: u32,
+        start: MovieStart,
+        path: PathBuf,
+    },
+    Playing {
+        player: InputPlayer,
+        length: u64,
+    },
+}
+
+impl MovieSession {
+    /// Starts recording from the current state, or restores the start state of a movie to play
+    pub(crate) fn start(
+        mode: MovieMode,
+        state: &mut EmulatorState,
+        power_on: bool,
+    ) -> Result<Self> {
+        match mode {
+            MovieMode::Record(path) => {
+                let start = if power_on {
+                    MovieStart::PowerOn
+                } else {
+                    MovieStart::SaveState(state.save_state()?)
+                };
+
+                log::info!("Recording movie to {}", path.display());
+
+                Ok(Self::Recording {
+                    recorder: InputRecorder::default(),
+                    rom_checksum: state.cpu.bus.rom_checksum(),
+                    start,
+                    path,
+                })
+            }
+            MovieMode::Play(movie) => {
+                movie.restore_start(state)?;
+
+                Ok(Self::Playing {
+                    player: InputPlayer::new(movie.inputs),
+                    length: movie.length,
+                })
+            }
+        }
+    }
+
+    pub(crate) fn step(&mut self, state: &mut EmulatorState) -> u8 {
+        match self {
+            Self::Recording { recorder, .. } => {
+                let cycles = state.step();
+                recorder.add_cycles(cycles);
+
+                cycles
+            }
+            Self::Playing { player, .. } => player.step(state),
+        }
+    }
+
+    /// Whether the end of a played movie was reached
+    pub(crate) fn is_finished(&self) -> bool {
+        match self {
+            Self::Recording { .. } => false,
+            Self::Playing { player, length } => player.cycles() >= *length,
+        }
+    }
+
+    /// Host input is recorded, or ignored during playback
+    pub(crate) fn handle_joypad_event(&mut self, state: &mut EmulatorState, event: JoypadEvent) {
+        if let Self::Recording { recorder, .. } = self {
+            recorder.handle_joypad_event(state, event);
+        }
+    }
+
+    /// Playback can not continue from a loaded state
+    pub(crate) fn check_load_state(&self) -> Result<()> {
+        match self {
+            Self::Recording { .. } => Ok(()),
+            Self::Playing { .. } => bail!("States can not be loaded during movie playback"),
+        }
+    }
+
+    /// Loading a state restarts a recording from the loaded state
+    pub(crate) fn restart_recording(&mut self, state: &EmulatorState) -> Result<()> {
+        if let Self::Recording {
+            recorder, start, ..
+        } = self
+        {
+            *recorder = InputRecorder::default();
+            *start = MovieStart::SaveState(state.save_state()?);
+            log::info!("Restarted movie recording from the loaded state");
+        }
+
+        Ok(())
+    }
+
+    /// Writes a recorded movie
+    pub(crate) fn finish(self) -> Result<()> {
+        let Self::Recording {
+            recorder,
+            rom_checksum,
+            start,
+            path,
+        } = self
+        else {
+            return Ok(());
+        };
+
+        let movie = Movie::from_recording(rom_checksum, start, recorder);
+
+        movie.save(&path)?;
+        log::info!("Saved movie to {}", path.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie(start: MovieStart) -> Movie {
+        let mut inputs = InputScript::default();
+        inputs.push(1_000, &[Button::Right, Button::A]);
+        inputs.push(70_224, &[Button::Down, Button::B, Button::Start]);
+        inputs.push(u64::from(u32::MAX) + 1, &[]);
+
+        Movie {
+            rom_checksum: 0xDEAD_BEEF,
+            start,
+            length: u64::from(u32::MAX) + 70_224,
+            inputs,
+        }
+    }
+
+    #[test]
+    fn power_on_movie_round_trips() {
+        let movie = movie(MovieStart::PowerOn);
+
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+    }
+
+    #[test]
+    fn save_state_movie_round_trips() {
+        let state = (0..=u8::MAX).cycle().take(1_000).collect();
+        let movie = movie(MovieStart::SaveState(state));
+
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()).unwrap(), movie);
+    }
+
+    #[test]
+    fn truncated_movie_is_rejected() {
+        let contents = movie(MovieStart::SaveState(vec![0x42; 16])).to_bytes();
+
+        for length in 0..contents.len() {
+            assert!(Movie::from_bytes(&contents[..length]).is_err());
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use crate::graphics::{write_png, GRAYSCALE_SHADES};
+use crate::memory::serial::SerialDevice;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+const PRINTER_MAGIC: [u8; 2] = [0x88, 0x33];
+/// Sent by the printer as response to the first byte after the checksum
+const PRINTER_ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0b0001;
+const STATUS_PRINTING: u8 = 0b0010;
+const STATUS_IMAGE_FULL: u8 = 0b0100;
+const STATUS_UNPROCESSED_DATA: u8 = 0b1000;
+
+/// Status packets answered with the printing flag after a print command
+const PRINT_BUSY_POLLS: u8 = 4;
+
+const PRINT_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = PRINT_WIDTH / 8;
+const TILE_BYTES: usize = 16;
+/// The printer memory holds up to 9 bands of 160x16 pixels
+const PRINTER_BUFFER_SIZE: usize = 0x2000 + 0x400;
+const PRINT_MARGINS_INDEX: usize = 1;
+const PRINT_PALETTE_INDEX: usize = 2;
+/// Lower nibble of the margins byte, the feed after printing
+const PRINT_MARGIN_AFTER: u8 = 0x0F;
+
+#[derive(Clone, Copy)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// Game Boy Printer attached to the link port, see https://gbdev.io/pandocs/Gameboy_Printer.html
+///
+/// Packets consist of the magic bytes, a command, a compression flag, the data length, the data
+/// and a checksum, followed by two bytes during which the printer sends its alive byte and status.
+/// Each print command prints the image data received since the last print. A print job may consist
+/// of several print commands without margin between them, and is saved as one PNG file once a
+/// print command feeds paper after printing.
+pub struct GameBoyPrinter {
+    output_dir: PathBuf,
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    /// Checksum transmitted at the end of the packet
+    received_checksum: u16,
+    status: u8,
+    busy_polls: u8,
+    /// Received 2bpp tile data, in rows of 20 tiles
+    image: Vec<u8>,
+    /// RGB pixels of the current print job
+    job: Vec<u8>,
+    printed_pages: usize,
+}
+
+impl GameBoyPrinter {
+    pub fn new(output_dir: &Path) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            busy_polls: 0,
+            image: Vec::new(),
+            job: Vec::new(),
+            printed_pages: 0,
+        }
+    }
+
+    /// Advances the packet state machine with a received byte and returns the byte sent back
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut response = 0x00;
+
+        self.state = match self.state {
+            PacketState::Magic(index) if byte == PRINTER_MAGIC[index] => {
+                if index + 1 == PRINTER_MAGIC.len() {
+                    PacketState::Command
+                } else {
+                    PacketState::Magic(index + 1)
+                }
+            }
+            // resynchronize on the first magic byte
+            PacketState::Magic(_) if byte == PRINTER_MAGIC[0] => PacketState::Magic(1),
+            PacketState::Magic(_) => PacketState::Magic(0),
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = u16::from(byte);
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 1 != 0;
+                self.checksum = self.checksum.wrapping_add(byte.into());
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = u16::from(byte);
+                self.checksum = self.checksum.wrapping_add(byte.into());
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= u16::from(byte) << 8;
+                self.checksum = self.checksum.wrapping_add(byte.into());
+                self.data.clear();
+
+                if self.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte.into());
+
+                if self.data.len() == usize::from(self.length) {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = u16::from(byte);
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= u16::from(byte) << 8;
+                PacketState::Alive
+            }
+            PacketState::Alive => {
+                response = PRINTER_ALIVE;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                self.process_packet();
+                response = self.status;
+                PacketState::Magic(0)
+            }
+        };
+
+        response
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            COMMAND_INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.busy_polls = 0;
+            }
+            COMMAND_DATA => {
+                let data = if self.compressed {
+                    decompress(&self.data)
+                } else {
+                    std::mem::take(&mut self.data)
+                };
+
+                self.image.extend(data);
+                self.image.truncate(PRINTER_BUFFER_SIZE);
+
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED_DATA;
+                }
+
+                if self.image.len() == PRINTER_BUFFER_SIZE {
+                    self.status |= STATUS_IMAGE_FULL;
+                }
+            }
+            COMMAND_PRINT => {
+                let argument = |index| self.data.get(index).copied().unwrap_or_default();
+                let margins = argument(PRINT_MARGINS_INDEX);
+                let palette = argument(PRINT_PALETTE_INDEX);
+
+                self.print(palette);
+
+                if margins & PRINT_MARGIN_AFTER != 0 {
+                    if let Err(error) = self.save_job() {
+                        log::error!("{error:?}");
+                    }
+                }
+
+                self.image.clear();
+                self.status &= !(STATUS_UNPROCESSED_DATA | STATUS_IMAGE_FULL);
+                self.status |= STATUS_PRINTING;
+                self.busy_polls = PRINT_BUSY_POLLS;
+            }
+            COMMAND_STATUS if self.busy_polls > 0 => {
+                self.busy_polls -= 1;
+
+                if self.busy_polls == 0 {
+                    self.status &= !STATUS_PRINTING;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Appends the buffered image with the palette of the print command applied to the print job
+    fn print(&mut self, palette: u8) {
+        let band_bytes = TILES_PER_ROW * TILE_BYTES;
+        let height = self.image.len() / band_bytes * 8;
+
+        // a palette of 0x00 is treated like the default 0xE4 by the printer
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let mut pixels = vec![0; PRINT_WIDTH * height * 3];
+
+        for y in 0..height {
+            for x in 0..PRINT_WIDTH {
+                let tile = (y / 8) * TILES_PER_ROW + x / 8;
+                let offset = tile * TILE_BYTES + (y % 8) * 2;
+                let bit = 7 - (x % 8);
+
+                let low = (self.image[offset] >> bit) & 1;
+                let high = (self.image[offset + 1] >> bit) & 1;
+                let color = (high << 1) | low;
+                let shade = (palette >> (color * 2)) & 0b11;
+
+                let index = (y * PRINT_WIDTH + x) * 3;
+                pixels[index..index + 3].copy_from_slice(&GRAYSCALE_SHADES[usize::from(shade)]);
+            }
+        }
+
+        self.job.extend(pixels);
+    }
+
+    /// Writes the print job to the next file name not taken by an earlier session
+    fn save_job(&mut self) -> Result<()> {
+        if self.job.is_empty() {
+            return Ok(());
+        }
+
+        let path = loop {
+            self.printed_pages += 1;
+            let path = self
+                .output_dir
+                .join(format!("print_{:03}.png", self.printed_pages));
+
+            if !path.exists() {
+                break path;
+            }
+        };
+
+        let height = self.job.len() / (PRINT_WIDTH * 3);
+        write_png(&path, PRINT_WIDTH as u32, height as u32, &self.job)?;
+        self.job.clear();
+
+        log::info!("Printed {}", path.display());
+
+        Ok(())
+    }
+}
+
+/// A print job without trailing margin is saved when the printer is disconnected
+impl Drop for GameBoyPrinter {
+    fn drop(&mut self) {
+        if let Err(error) = self.save_job() {
+            log::error!("{error:?}");
+        }
+    }
+}
+
+impl SerialDevice for GameBoyPrinter {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.receive(byte)
+    }
+
+    /// The printer never provides the clock
+    fn poll_external(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Run-length encoding: a set bit 7 repeats the next byte `(n & 0x7F) + 2` times, otherwise the
+/// next `n + 1` bytes are copied
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bytes = data.iter().copied();
+
+    while let Some(control) = bytes.next() {
+        if control & 0x80 != 0 {
+            let Some(value) = bytes.next() else {
+                break;
+            };
+
+            let count = usize::from(control & 0x7F) + 2;
+            output.extend(std::iter::repeat_n(value, count));
+        } else {
+            output.extend(bytes.by_ref().take(usize::from(control) + 1));
+        }
+    }
+
+    output
+}
@@ -0,0 +1,185 @@
+use crate::emulator::EmulatorState;
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+
+/// A snapshot is taken every 2 frames
+pub const REWIND_INTERVAL: u32 = 2;
+/// Snapshots for 20 seconds of emulation
+pub const REWIND_CAPACITY: usize = 600;
+/// Upper bound for the compressed deltas, the oldest snapshots are dropped beyond it
+const REWIND_MEMORY_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Difference between a snapshot and the next newer one
+struct Delta {
+    /// Length of the older snapshot
+    length: usize,
+    /// Compressed XOR of both snapshots
+    data: Vec<u8>,
+}
+
+impl Delta {
+    fn new(older: &[u8], newer: &[u8]) -> Self {
+        Self {
+            length: older.len(),
+            data: compress(&xor(older, newer)),
+        }
+    }
+
+    /// Restores the older snapshot from the newer one
+    fn apply(&self, newer: &[u8]) -> Result<Vec<u8>> {
+        let mut older = xor(newer, &decompress(&self.data)?);
+        older.truncate(self.length);
+
+        Ok(older)
+    }
+}
+
+/// Ring of periodic snapshots to step backwards through the emulation.
+///
+/// Only the newest snapshot is kept as a whole save state. Every older snapshot is stored as
+/// compressed difference to its successor, so most of a snapshot is a run of zeros. Rewinding
+/// applies the differences from the newest snapshot backwards, which keeps dropping the oldest
+/// snapshot free.
+#[derive(Default)]
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    memory_usage: usize,
+}
+
+impl RewindBuffer {
+    /// Takes a snapshot of the state, dropping the oldest one once the buffer is full
+    pub fn push(&mut self, state: &EmulatorState) -> Result<()> {
+        let snapshot = state.save_state()?;
+
+        if let Some(previous) = self.latest.replace(snapshot) {
+            let delta = Delta::new(&previous, self.latest.as_ref().unwrap());
+
+            self.memory_usage += delta.data.len();
+            self.deltas.push_back(delta);
+        }
+
+        while self.deltas.len() > REWIND_CAPACITY || self.memory_usage > REWIND_MEMORY_LIMIT {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+
+            self.memory_usage -= oldest.data.len();
+        }
+
+        Ok(())
+    }
+
+    /// Restores the newest snapshot and removes it, so that the next call goes further back.
+    /// Returns `false` once no snapshot is left.
+    pub fn pop(&mut self, state: &mut EmulatorState) -> Result<bool> {
+        let Some(snapshot) = self.latest.take() else {
+            return Ok(false);
+        };
+
+        state.load_state(&snapshot)?;
+
+        if let Some(delta) = self.deltas.pop_back() {
+            self.memory_usage -= delta.data.len();
+
+            self.latest = Some(delta.apply(&snapshot)?);
+        }
+
+        Ok(true)
+    }
+}
+
+/// XOR of both buffers, the shorter one is padded with zeros
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let length = a.len().max(b.len());
+
+    (0..length)
+        .map(|i| a.get(i).copied().unwrap_or_default() ^ b.get(i).copied().unwrap_or_default())
+        .collect()
+}
+
+/// Encodes the data as blocks of a zero run length and a literal length, both u16 little endian,
+/// followed by the literal bytes
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        let zeros = data[position..]
+            .iter()
+            .take(usize::from(u16::MAX))
+            .take_while(|&&byte| byte == 0)
+            .count();
+        position += zeros;
+
+        let literals = data[position..]
+            .iter()
+            .take(usize::from(u16::MAX))
+            .take_while(|&&byte| byte != 0)
+            .count();
+
+        output.extend_from_slice(&(zeros as u16).to_le_bytes());
+        output.extend_from_slice(&(literals as u16).to_le_bytes());
+        output.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+
+    output
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        let Some(header) = data.get(position..position + 4) else {
+            bail!("Truncated rewind snapshot");
+        };
+
+        let zeros = usize::from(u16::from_le_bytes([header[0], header[1]]));
+        let literals = usize::from(u16::from_le_bytes([header[2], header[3]]));
+        position += 4;
+
+        let Some(bytes) = data.get(position..position + literals) else {
+            bail!("Truncated rewind snapshot");
+        };
+
+        output.resize(output.len() + zeros, 0);
+        output.extend_from_slice(bytes);
+        position += literals;
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_restores_shorter_and_longer_snapshots() {
+        let short = vec![1, 0, 0, 2, 3];
+        let long = vec![1, 0, 4, 2, 0, 0, 5, 6];
+
+        assert_eq!(Delta::new(&short, &long).apply(&long).unwrap(), short);
+        assert_eq!(Delta::new(&long, &short).apply(&short).unwrap(), long);
+    }
+
+    #[test]
+    fn long_runs_round_trip() {
+        let mut data = vec![0; 3 * usize::from(u16::MAX) + 7];
+        data.extend(std::iter::repeat_n(0xAB, 2 * usize::from(u16::MAX) + 3));
+        data.push(0);
+
+        assert_eq!(decompress(&compress(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_stream_is_rejected() {
+        let compressed = compress(&[0, 0, 1, 2, 3]);
+
+        for length in 1..compressed.len() {
+            assert!(decompress(&compressed[..length]).is_err());
+        }
+    }
+}
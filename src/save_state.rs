@@ -0,0 +1,65 @@
+use crate::emulator::EmulatorState;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Identifies save state files of this emulator
+const SAVE_STATE_MAGIC: &[u8; 4] = b"GBSS";
+/// Bumped whenever the layout of the serialized `EmulatorState` changes
+const SAVE_STATE_VERSION: u16 = 9;
+/// Magic, format version and CRC32 of the cartridge ROM
+const SAVE_STATE_HEADER_SIZE: usize = 10;
+
+/// Save states are stored next to the rom as `<rom>.ss<slot>`
+pub fn save_state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    rom_path.with_extension(format!("ss{slot}"))
+}
+
+impl EmulatorState {
+    /// Serializes the whole emulator state, except the cartridge ROM which is identified by its
+    /// checksum in the header
+    pub fn save_state(&self) -> Result<Vec<u8>> {
+        let mut contents = Vec::with_capacity(SAVE_STATE_HEADER_SIZE);
+
+        contents.extend_from_slice(SAVE_STATE_MAGIC);
+        contents.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        contents.extend_from_slice(&self.cpu.bus.rom_checksum().to_le_bytes());
+
+        bincode::serialize_into(&mut contents, self).context("Failed to serialize save state")?;
+
+        Ok(contents)
+    }
+
+    /// Restores a state created by `save_state`. States of other ROMs or format versions are
+    /// rejected and leave the current state untouched.
+    pub fn load_state(&mut self, contents: &[u8]) -> Result<()> {
+        if contents.len() < SAVE_STATE_HEADER_SIZE || &contents[..4] != SAVE_STATE_MAGIC {
+            bail!("Not a save state file");
+        }
+
+        let version = u16::from_le_bytes([contents[4], contents[5]]);
+
+        if version != SAVE_STATE_VERSION {
+            bail!("Unsupported save state version {version}, expected {SAVE_STATE_VERSION}");
+        }
+
+        let rom_checksum = u32::from_le_bytes(contents[6..10].try_into()?);
+
+        if rom_checksum != self.cpu.bus.rom_checksum() {
+            bail!(
+                "Save state belongs to a different ROM (checksum 0x{:08X}, expected 0x{:08X})",
+                rom_checksum,
+                self.cpu.bus.rom_checksum()
+            );
+        }
+
+        let mut state: EmulatorState = bincode::deserialize(&contents[SAVE_STATE_HEADER_SIZE..])
+            .context("Failed to deserialize save state")?;
+
+        state.cpu.bus.restore_rom(&self.cpu.bus);
+        state.cpu.bus.restore_rtc_clock(&self.cpu.bus);
+        state.cpu.bus.restore_serial_device(&self.cpu.bus);
+        *self = state;
+
+        Ok(())
+    }
+}
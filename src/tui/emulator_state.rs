@@ -176,7 +176,7 @@ impl Page for EmulatorStateView {
             }
             AppEvent::StateEvent(emulator_state) => {
                 let mut emulator_snapshot = self.emulator_snapshot.write().unwrap();
-                *emulator_snapshot = emulator_state;
+                *emulator_snapshot = *emulator_state;
             }
         }
     }
@@ -63,7 +63,7 @@ impl Tab {
 
 pub(super) enum AppEvent {
     UiEvent(Event),
-    StateEvent(EmulatorState),
+    StateEvent(Box<EmulatorState>),
 }
 
 pub struct Debugger {
@@ -149,26 +149,21 @@ fn run_tui_thread(
             }
         };
 
-        match &event {
-            AppEvent::UiEvent(ui_event) => {
-                if let Event::Key(key) = ui_event {
-                    match key.code {
-                        KeyCode::Char('x') => {
-                            terminated.store(true, Ordering::Relaxed);
-                            break;
-                        }
-                        KeyCode::Char('p') => {
-                            let is_paused = paused.load(Ordering::Relaxed);
-                            paused.store(!is_paused, Ordering::Relaxed);
-                        }
-                        KeyCode::Tab | KeyCode::Char('\t') => {
-                            tab.next_tab(emulator_state_view.clone(), logging_view.clone())
-                        }
-                        _ => (),
-                    }
+        if let AppEvent::UiEvent(Event::Key(key)) = &event {
+            match key.code {
+                KeyCode::Char('x') => {
+                    terminated.store(true, Ordering::Relaxed);
+                    break;
+                }
+                KeyCode::Char('p') => {
+                    let is_paused = paused.load(Ordering::Relaxed);
+                    paused.store(!is_paused, Ordering::Relaxed);
+                }
+                KeyCode::Tab | KeyCode::Char('\t') => {
+                    tab.next_tab(emulator_state_view.clone(), logging_view.clone())
                 }
+                _ => (),
             }
-            _ => (),
         }
 
         tab.handle_event(event);
@@ -210,7 +205,7 @@ fn run_snapshot_thread(
         };
 
         let _ = snapshot_sender
-            .send(AppEvent::StateEvent(emulator))
+            .send(AppEvent::StateEvent(Box::new(emulator)))
             .map_err(|_| log::error!("Error while submitting StateEvent with new snapshot."));
 
         thread::sleep(Duration::from_millis(SNAPSHOT_DELAY_MS));
@@ -0,0 +1,53 @@
+mod common;
+
+/// The longest test, cpu_instrs, takes about a minute of emulated time
+const MAX_FRAMES: u32 = 60 * 90;
+
+/// Blargg's test roms print their results to the serial port, ending with "Passed" or "Failed"
+fn run_serial_test(path: &str) {
+    let mut game_boy = common::load_rom(path);
+
+    let serial = common::capture_serial(&mut game_boy);
+    let mut output = String::new();
+
+    for _ in 0..MAX_FRAMES {
+        game_boy.step_frame();
+        output.push_str(&String::from_utf8_lossy(&serial.take()));
+
+        if output.contains("Passed") || output.contains("Failed") {
+            break;
+        }
+    }
+
+    assert!(output.contains("Passed"), "{path}:\n{output}");
+}
+
+macro_rules! serial_tests {
+    ($($name:ident: $path:literal,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the test rom suite, run with --ignored"]
+            fn $name() {
+                run_serial_test($path);
+            }
+        )*
+    };
+}
+
+serial_tests! {
+    cpu_instrs_01_special: "blargg/cpu_instrs/individual/01-special.gb",
+    cpu_instrs_02_interrupts: "blargg/cpu_instrs/individual/02-interrupts.gb",
+    cpu_instrs_03_op_sp_hl: "blargg/cpu_instrs/individual/03-op sp,hl.gb",
+    cpu_instrs_04_op_r_imm: "blargg/cpu_instrs/individual/04-op r,imm.gb",
+    cpu_instrs_05_op_rp: "blargg/cpu_instrs/individual/05-op rp.gb",
+    cpu_instrs_06_ld_r_r: "blargg/cpu_instrs/individual/06-ld r,r.gb",
+    cpu_instrs_07_jr_jp_call_ret_rst: "blargg/cpu_instrs/individual/07-jr,jp,call,ret,rst.gb",
+    cpu_instrs_08_misc_instrs: "blargg/cpu_instrs/individual/08-misc instrs.gb",
+    cpu_instrs_09_op_r_r: "blargg/cpu_instrs/individual/09-op r,r.gb",
+    cpu_instrs_10_bit_ops: "blargg/cpu_instrs/individual/10-bit ops.gb",
+    cpu_instrs_11_op_a_hl: "blargg/cpu_instrs/individual/11-op a,(hl).gb",
+    instr_timing: "blargg/instr_timing/instr_timing.gb",
+    mem_timing_01_read_timing: "blargg/mem_timing/individual/01-read_timing.gb",
+    mem_timing_02_write_timing: "blargg/mem_timing/individual/02-write_timing.gb",
+    mem_timing_03_modify_timing: "blargg/mem_timing/individual/03-modify_timing.gb",
+}
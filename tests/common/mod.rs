@@ -1,62 +1,68 @@
+#![allow(dead_code)]
+use anyhow::{Context, Result};
+use gb_emulator::{CaptureBuffer, GameBoy};
 use std::fs::{self, File};
-use std::io::{copy, BufWriter};
-use std::path::PathBuf;
+use std::io::{copy, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Once;
+use std::sync::{Arc, Mutex, OnceLock};
 
 const DOWNLOAD_URL: &str =
     "https://github.com/c-sp/game-boy-test-roms/releases/download/v7.0/game-boy-test-roms-v7.0.zip";
 const DOWNLOAD_PATH: &str = "data/test-roms";
+/// Path of an extracted `game-boy-test-roms` release, used instead of downloading it
+const TEST_ROMS_ENV: &str = "GB_TEST_ROMS";
 
-static INIT: Once = Once::new();
+static TEST_ROMS: OnceLock<PathBuf> = OnceLock::new();
 
-fn download_test_roms() {
-    if fs::exists("data/test-roms").unwrap() {
+fn download_test_roms() -> Result<()> {
+    if fs::exists(DOWNLOAD_PATH)? {
         println!("Test roms already present, skipping Download...");
-        return;
+        return Ok(());
     }
 
     let data_dir = PathBuf::from_str("data").unwrap();
     if !data_dir.exists() {
-        fs::create_dir(data_dir).unwrap();
+        fs::create_dir(data_dir)?;
     }
 
     println!("Downloading test roms...");
 
-    let mut response = ureq::get(DOWNLOAD_URL).call().unwrap();
+    let mut response = ureq::get(DOWNLOAD_URL).call()?;
 
     let zip_path = format!("{}.zip", DOWNLOAD_PATH);
-    let mut output = BufWriter::new(fs::File::create(&zip_path).unwrap());
+    let mut output = BufWriter::new(fs::File::create(&zip_path)?);
 
-    copy(&mut response.body_mut().as_reader(), &mut output).unwrap();
+    copy(&mut response.body_mut().as_reader(), &mut output)?;
+    drop(output);
 
-    let zip_file = fs::File::open(zip_path).unwrap();
+    let zip_file = fs::File::open(zip_path)?;
 
-    extract_zip_archive(zip_file);
+    extract_zip_archive(zip_file)
 }
 
-fn extract_zip_archive(file: File) {
-    let mut archive = zip::ZipArchive::new(file).unwrap();
+fn extract_zip_archive(file: File) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(file)?;
 
     let target_dir = PathBuf::from_str(DOWNLOAD_PATH).unwrap();
 
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
+        let mut file = archive.by_index(i)?;
         let outpath = match file.enclosed_name() {
             Some(path) => target_dir.join(path),
             None => continue,
         };
 
         if file.is_dir() {
-            fs::create_dir_all(&outpath).unwrap();
+            fs::create_dir_all(&outpath)?;
         } else {
             if let Some(p) = outpath.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(p).unwrap();
+                    fs::create_dir_all(p)?;
                 }
             }
-            let mut outfile = fs::File::create(&outpath).unwrap();
-            copy(&mut file, &mut outfile).unwrap();
+            let mut outfile = fs::File::create(&outpath)?;
+            copy(&mut file, &mut outfile)?;
         }
 
         // Get and Set permissions
@@ -65,14 +71,107 @@ fn extract_zip_archive(file: File) {
             use std::os::unix::fs::PermissionsExt;
 
             if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode)).unwrap();
+                fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
             }
         }
     }
+
+    Ok(())
+}
+
+/// Root of the test rom suite, taken from `GB_TEST_ROMS` or downloaded on first use. Panics if
+/// the roms are not available.
+pub fn test_roms_dir() -> &'static Path {
+    TEST_ROMS.get_or_init(|| {
+        if let Some(path) = std::env::var_os(TEST_ROMS_ENV) {
+            let path = PathBuf::from(path);
+            assert!(
+                path.is_dir(),
+                "{TEST_ROMS_ENV} is set to {}, which is not a directory",
+                path.display()
+            );
+
+            return path;
+        }
+
+        download_test_roms()
+            .with_context(|| {
+                format!("Test roms are not available, set {TEST_ROMS_ENV} to run offline")
+            })
+            .unwrap();
+
+        PathBuf::from(DOWNLOAD_PATH)
+    })
 }
 
-pub fn initialize() {
-    INIT.call_once(|| {
-        download_test_roms();
-    });
+/// Loads a rom of the suite
+pub fn load_rom(path: &str) -> GameBoy {
+    let path = test_roms_dir().join(path);
+    let contents = fs::read(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))
+        .unwrap();
+
+    GameBoy::new(&contents).unwrap()
+}
+
+/// Entry point of `build_rom` programs, right after the cartridge header
+pub const PROGRAM_START: usize = 0x150;
+const ROM_SIZE: usize = 0x8000;
+const HEADER_ENTRY: usize = 0x100;
+const HEADER_CHECKSUM: usize = 0x14D;
+
+/// Builds a 32 KiB ROM-only cartridge running `program`, for tests that do not need the test rom
+/// suite
+pub fn build_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; ROM_SIZE];
+
+    // NOP ; JP 0x0150
+    rom[HEADER_ENTRY..HEADER_ENTRY + 4].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+
+    rom[HEADER_CHECKSUM] = rom[0x134..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+
+    rom
+}
+
+/// Connects a buffer to the serial port, which collects the output of blargg's test roms
+pub fn capture_serial(game_boy: &mut GameBoy) -> CaptureBuffer {
+    let output = CaptureBuffer::default();
+    game_boy.connect_serial(Arc::new(Mutex::new(output.clone())));
+
+    output
+}
+
+/// Decodes a reference image of the suite to 8-bit RGB pixels
+pub fn load_reference_image(path: &str) -> Vec<u8> {
+    let path = test_roms_dir().join(path);
+    let contents = fs::read(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))
+        .unwrap();
+
+    let mut decoder = png::Decoder::new(Cursor::new(contents));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info().unwrap();
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut buffer).unwrap();
+    buffer.truncate(info.buffer_size());
+
+    match info.color_type {
+        png::ColorType::Rgb => buffer,
+        png::ColorType::Rgba => buffer
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&gray| [gray; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0]; 3])
+            .collect(),
+        color_type => panic!("Unsupported color type {color_type:?}"),
+    }
 }
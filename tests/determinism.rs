@@ -0,0 +1,62 @@
+mod common;
+
+use gb_emulator::{GameBoy, InputScript};
+
+/// Multiple of both budgets, so that both runs end at the same instruction
+const RUN_CYCLES: u64 = 4_000_000;
+const INPUT_SCRIPT: &str = "
+500000 A START
+1500000 DOWN
+2500000
+";
+
+/// Starts a square wave, then keeps mixing the joypad state and DIV into the background palette
+/// and the pitch, so the frames and the audio depend on the timing of every input
+#[rustfmt::skip]
+const PROGRAM: [u8; 48] = [
+    0x3E, 0x80, 0xE0, 0x26, // LD A,0x80 ; LDH (NR52),A
+    0x3E, 0x77, 0xE0, 0x24, // LD A,0x77 ; LDH (NR50),A
+    0x3E, 0xFF, 0xE0, 0x25, // LD A,0xFF ; LDH (NR51),A
+    0x3E, 0x80, 0xE0, 0x16, // LD A,0x80 ; LDH (NR21),A
+    0x3E, 0xF0, 0xE0, 0x17, // LD A,0xF0 ; LDH (NR22),A
+    0x3E, 0x87, 0xE0, 0x19, // LD A,0x87 ; LDH (NR24),A
+    // loop:
+    0x3E, 0x20, 0xE0, 0x00, // LD A,0x20 ; LDH (P1),A
+    0xF0, 0x00, 0x47,       // LDH A,(P1) ; LD B,A
+    0x3E, 0x10, 0xE0, 0x00, // LD A,0x10 ; LDH (P1),A
+    0xF0, 0x00, 0xA8, 0x4F, // LDH A,(P1) ; XOR B ; LD C,A
+    0xF0, 0x04, 0xA9,       // LDH A,(DIV) ; XOR C
+    0xE0, 0x47, 0xE0, 0x18, // LDH (BGP),A ; LDH (NR23),A
+    0x18, 0xE8,             // JR loop
+];
+
+/// Framebuffer, audio and save state after running with the given cycle budget per call
+fn run(budget: u64) -> (Vec<u8>, Vec<f32>, Vec<u8>) {
+    let mut game_boy = GameBoy::new(&common::build_rom(&PROGRAM)).unwrap();
+    game_boy.set_input_script(INPUT_SCRIPT.parse::<InputScript>().unwrap());
+
+    let mut audio = Vec::new();
+
+    while game_boy.cycles() < RUN_CYCLES {
+        game_boy.run_cycles(budget);
+        audio.extend(game_boy.audio_samples());
+    }
+
+    (
+        game_boy.framebuffer().0.to_vec(),
+        audio,
+        game_boy.save_state().unwrap(),
+    )
+}
+
+#[test]
+fn same_inputs_produce_identical_output() {
+    let first = run(1_000);
+
+    let (framebuffer, audio, _) = &first;
+    assert!(framebuffer.iter().any(|&shade| shade != framebuffer[0]));
+    assert!(audio.iter().any(|&sample| sample != 0.0));
+
+    assert!(run(1_000) == first);
+    assert!(run(1_000_000) == first);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+/// The image is complete once the rom executes `LD B,B`, well within this many frames
+const MAX_FRAMES: u32 = 60 * 5;
+
+#[test]
+#[ignore = "needs the test rom suite, run with --ignored"]
+fn dmg_acid2() {
+    let mut game_boy = common::load_rom("dmg-acid2/dmg-acid2.gb");
+
+    for _ in 0..MAX_FRAMES {
+        game_boy.step_frame();
+    }
+
+    let expected = common::load_reference_image("dmg-acid2/dmg-acid2-dmg.png");
+    let actual = game_boy.framebuffer().to_rgb();
+
+    let mismatches = actual
+        .chunks_exact(3)
+        .zip(expected.chunks_exact(3))
+        .filter(|(actual, expected)| actual != expected)
+        .count();
+
+    assert_eq!(actual.len(), expected.len());
+    assert_eq!(
+        mismatches, 0,
+        "{mismatches} pixels differ from the reference"
+    );
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use gb_emulator::CLOCK_SPEED;
+
+/// Mooneye's test roms finish within a few seconds of emulated time
+const MAX_CYCLES: u64 = 20 * CLOCK_SPEED as u64;
+/// B, C, D, E, H and L after a passed test, a failed test sets them all to 0x42
+const PASSED_FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+/// Mooneye's test roms execute `LD B,B` once they are done, with the result in the registers
+fn run_breakpoint_test(path: &str) {
+    let mut game_boy = common::load_rom(path);
+
+    let mut cycles = 0;
+
+    while !game_boy.hit_breakpoint() {
+        assert!(cycles < MAX_CYCLES, "{path}: timed out");
+        cycles += u64::from(game_boy.step());
+    }
+
+    let registers = game_boy.registers();
+    let values = [
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ];
+
+    assert_eq!(values, PASSED_FIBONACCI, "{path}: {registers:02X?}");
+}
+
+macro_rules! breakpoint_tests {
+    ($($name:ident: $path:literal,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the test rom suite, run with --ignored"]
+            fn $name() {
+                run_breakpoint_test($path);
+            }
+        )*
+    };
+}
+
+breakpoint_tests! {
+    add_sp_e_timing: "mooneye-test-suite/acceptance/add_sp_e_timing.gb",
+    call_cc_timing: "mooneye-test-suite/acceptance/call_cc_timing.gb",
+    call_timing: "mooneye-test-suite/acceptance/call_timing.gb",
+    di_timing_gs: "mooneye-test-suite/acceptance/di_timing-GS.gb",
+    div_timing: "mooneye-test-suite/acceptance/div_timing.gb",
+    ei_sequence: "mooneye-test-suite/acceptance/ei_sequence.gb",
+    ei_timing: "mooneye-test-suite/acceptance/ei_timing.gb",
+    halt_ime0_ei: "mooneye-test-suite/acceptance/halt_ime0_ei.gb",
+    halt_ime0_nointr_timing: "mooneye-test-suite/acceptance/halt_ime0_nointr_timing.gb",
+    halt_ime1_timing: "mooneye-test-suite/acceptance/halt_ime1_timing.gb",
+    if_ie_registers: "mooneye-test-suite/acceptance/if_ie_registers.gb",
+    intr_timing: "mooneye-test-suite/acceptance/intr_timing.gb",
+    jp_cc_timing: "mooneye-test-suite/acceptance/jp_cc_timing.gb",
+    jp_timing: "mooneye-test-suite/acceptance/jp_timing.gb",
+    ld_hl_sp_e_timing: "mooneye-test-suite/acceptance/ld_hl_sp_e_timing.gb",
+    oam_dma_restart: "mooneye-test-suite/acceptance/oam_dma_restart.gb",
+    oam_dma_start: "mooneye-test-suite/acceptance/oam_dma_start.gb",
+    oam_dma_timing: "mooneye-test-suite/acceptance/oam_dma_timing.gb",
+    pop_timing: "mooneye-test-suite/acceptance/pop_timing.gb",
+    push_timing: "mooneye-test-suite/acceptance/push_timing.gb",
+    rapid_di_ei: "mooneye-test-suite/acceptance/rapid_di_ei.gb",
+    ret_cc_timing: "mooneye-test-suite/acceptance/ret_cc_timing.gb",
+    ret_timing: "mooneye-test-suite/acceptance/ret_timing.gb",
+    reti_intr_timing: "mooneye-test-suite/acceptance/reti_intr_timing.gb",
+    reti_timing: "mooneye-test-suite/acceptance/reti_timing.gb",
+    rst_timing: "mooneye-test-suite/acceptance/rst_timing.gb",
+    bits_mem_oam: "mooneye-test-suite/acceptance/bits/mem_oam.gb",
+    bits_reg_f: "mooneye-test-suite/acceptance/bits/reg_f.gb",
+    bits_unused_hwio_gs: "mooneye-test-suite/acceptance/bits/unused_hwio-GS.gb",
+    instr_daa: "mooneye-test-suite/acceptance/instr/daa.gb",
+    interrupts_ie_push: "mooneye-test-suite/acceptance/interrupts/ie_push.gb",
+    oam_dma_basic: "mooneye-test-suite/acceptance/oam_dma/basic.gb",
+    oam_dma_reg_read: "mooneye-test-suite/acceptance/oam_dma/reg_read.gb",
+    oam_dma_sources_gs: "mooneye-test-suite/acceptance/oam_dma/sources-GS.gb",
+    ppu_stat_irq_blocking: "mooneye-test-suite/acceptance/ppu/stat_irq_blocking.gb",
+    ppu_stat_lyc_onoff: "mooneye-test-suite/acceptance/ppu/stat_lyc_onoff.gb",
+    ppu_vblank_stat_intr_gs: "mooneye-test-suite/acceptance/ppu/vblank_stat_intr-GS.gb",
+    serial_boot_sclk_align_dmg_abc_mgb: "mooneye-test-suite/acceptance/serial/boot_sclk_align-dmgABCmgb.gb",
+    timer_div_write: "mooneye-test-suite/acceptance/timer/div_write.gb",
+    timer_rapid_toggle: "mooneye-test-suite/acceptance/timer/rapid_toggle.gb",
+    timer_tim00: "mooneye-test-suite/acceptance/timer/tim00.gb",
+    timer_tim00_div_trigger: "mooneye-test-suite/acceptance/timer/tim00_div_trigger.gb",
+    timer_tim01: "mooneye-test-suite/acceptance/timer/tim01.gb",
+    timer_tim01_div_trigger: "mooneye-test-suite/acceptance/timer/tim01_div_trigger.gb",
+    timer_tim10: "mooneye-test-suite/acceptance/timer/tim10.gb",
+    timer_tim10_div_trigger: "mooneye-test-suite/acceptance/timer/tim10_div_trigger.gb",
+    timer_tim11: "mooneye-test-suite/acceptance/timer/tim11.gb",
+    timer_tim11_div_trigger: "mooneye-test-suite/acceptance/timer/tim11_div_trigger.gb",
+    timer_tima_reload: "mooneye-test-suite/acceptance/timer/tima_reload.gb",
+    timer_tima_write_reloading: "mooneye-test-suite/acceptance/timer/tima_write_reloading.gb",
+    timer_tma_write_reloading: "mooneye-test-suite/acceptance/timer/tma_write_reloading.gb",
+    mbc1_bits_bank1: "mooneye-test-suite/emulator-only/mbc1/bits_bank1.gb",
+    mbc1_bits_bank2: "mooneye-test-suite/emulator-only/mbc1/bits_bank2.gb",
+    mbc1_bits_mode: "mooneye-test-suite/emulator-only/mbc1/bits_mode.gb",
+    mbc1_bits_ramg: "mooneye-test-suite/emulator-only/mbc1/bits_ramg.gb",
+    mbc1_ram_64kb: "mooneye-test-suite/emulator-only/mbc1/ram_64kb.gb",
+    mbc1_ram_256kb: "mooneye-test-suite/emulator-only/mbc1/ram_256kb.gb",
+    mbc1_rom_512kb: "mooneye-test-suite/emulator-only/mbc1/rom_512kb.gb",
+    mbc1_rom_1mb: "mooneye-test-suite/emulator-only/mbc1/rom_1Mb.gb",
+    mbc1_rom_2mb: "mooneye-test-suite/emulator-only/mbc1/rom_2Mb.gb",
+    mbc1_rom_4mb: "mooneye-test-suite/emulator-only/mbc1/rom_4Mb.gb",
+    mbc1_rom_8mb: "mooneye-test-suite/emulator-only/mbc1/rom_8Mb.gb",
+    mbc1_rom_16mb: "mooneye-test-suite/emulator-only/mbc1/rom_16Mb.gb",
+    mbc1_multicart_rom_8mb: "mooneye-test-suite/emulator-only/mbc1/multicart_rom_8Mb.gb",
+    mbc2_bits_ramg: "mooneye-test-suite/emulator-only/mbc2/bits_ramg.gb",
+    mbc2_bits_romb: "mooneye-test-suite/emulator-only/mbc2/bits_romb.gb",
+    mbc2_bits_unused: "mooneye-test-suite/emulator-only/mbc2/bits_unused.gb",
+    mbc2_ram: "mooneye-test-suite/emulator-only/mbc2/ram.gb",
+    mbc2_rom_512kb: "mooneye-test-suite/emulator-only/mbc2/rom_512kb.gb",
+    mbc2_rom_1mb: "mooneye-test-suite/emulator-only/mbc2/rom_1Mb.gb",
+    mbc2_rom_2mb: "mooneye-test-suite/emulator-only/mbc2/rom_2Mb.gb",
+    mbc5_rom_512kb: "mooneye-test-suite/emulator-only/mbc5/rom_512kb.gb",
+    mbc5_rom_1mb: "mooneye-test-suite/emulator-only/mbc5/rom_1Mb.gb",
+    mbc5_rom_2mb: "mooneye-test-suite/emulator-only/mbc5/rom_2Mb.gb",
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use gb_emulator::GameBoy;
+
+const SOURCE_BYTE: u8 = 0x5A;
+const RUN_CYCLES: u64 = 100_000;
+/// ROM bank 1, far away from the program
+const LANDING_ADDRESS: usize = 0x4000;
+
+/// Fills 0xC000-0xC09F with the alternating `pattern`, turns the LCD off so that OAM is not blocked
+/// by the PPU and copies `routine` to HRAM, where it runs with HL pointing to OAM and A holding the
+/// DMA source. The CPU can't fetch from ROM while the DMA uses the external bus.
+fn hram_routine_rom(pattern: [u8; 2], routine: &[u8]) -> Vec<u8> {
+    let mut program = vec![
+        0xF3, // DI
+        0x31, 0xFE, 0xFF, // LD SP,0xFFFE
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH (0x40),A
+        0x21, 0x00, 0xC0, // LD HL,0xC000
+        0x06, 80, // LD B,80
+        0x3E, pattern[0], // LD A,pattern[0]
+        0x22,       // LD (HL+),A
+        0x3E, pattern[1], // LD A,pattern[1]
+        0x22,       // LD (HL+),A
+        0x05,       // DEC B
+        0x20, 0xF7, // JR NZ,-9
+        0x21, 0x80, 0xFF, // LD HL,0xFF80
+    ];
+
+    for byte in routine.iter().chain(&[0x18, 0xFE]) {
+        program.extend([0x36, *byte, 0x2C]); // LD (HL),byte ; INC L
+    }
+
+    program.extend([
+        0x21, 0x00, 0xFE, // LD HL,0xFE00
+        0x3E, 0xC0, // LD A,0xC0
+        0xC3, 0x80, 0xFF, // JP 0xFF80
+    ]);
+
+    common::build_rom(&program)
+}
+
+fn run(rom: &[u8]) -> GameBoy {
+    let mut game_boy = GameBoy::new(rom).unwrap();
+    game_boy.run_cycles(RUN_CYCLES);
+
+    game_boy
+}
+
+fn run_hram_routine(routine: &[u8]) -> u8 {
+    run(&hram_routine_rom([SOURCE_BYTE; 2], routine))
+        .registers()
+        .a
+}
+
+/// `LDH (0x46),A` writes in its third M-cycle, `LD A,(HL)` reads two M-cycles later, after the
+/// startup delay
+#[test]
+fn oam_is_blocked_after_startup_delay() {
+    // LDH (0x46),A ; LD A,(HL)
+    assert_eq!(run_hram_routine(&[0xE0, 0x46, 0x7E]), 0xFF);
+}
+
+/// The transfer ends 162 M-cycles after the write: LD B,39 and the loop take 2 + 39 * 4 - 1
+/// M-cycles, followed by the NOPs and the fetch of `LD A,(HL)`
+#[test]
+fn oam_is_accessible_after_transfer() {
+    let routine = |nops: usize| {
+        let mut routine = vec![0xE0, 0x46, 0x06, 39, 0x05, 0x20, 0xFD];
+        routine.extend(std::iter::repeat_n(0x00, nops));
+        routine.push(0x7E);
+        routine
+    };
+
+    assert_eq!(run_hram_routine(&routine(2)), 0xFF);
+    assert_eq!(run_hram_routine(&routine(3)), SOURCE_BYTE);
+}
+
+/// Jumping to ROM during the transfer executes the bytes being copied instead, one per M-cycle. The
+/// source alternates `LD B,0x40` and `LD B,B`, so an operand read in its own M-cycle always loads
+/// 0x40.
+#[test]
+fn operands_are_read_in_their_own_cycle() {
+    let mut rom = hram_routine_rom(
+        [0x06, 0x40],
+        &[
+            0xE0, 0x46, // LDH (0x46),A
+            0x06, 0x00, // LD B,0
+            0xC3, 0x00, 0x40, // JP LANDING_ADDRESS
+        ],
+    );
+    // NOPs until the transfer is over, then loop
+    rom[LANDING_ADDRESS + 0x100..LANDING_ADDRESS + 0x102].copy_from_slice(&[0x18, 0xFE]);
+
+    assert_eq!(run(&rom).registers().b, 0x40);
+}
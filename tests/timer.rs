@@ -0,0 +1,62 @@
+mod common;
+
+use gb_emulator::GameBoy;
+
+const RUN_CYCLES: u64 = 10_000;
+const TIMA_MODULO: u8 = 0xAB;
+
+/// Runs `setup`, resets DIV, runs `after_reset` and waits `nops` M-cycles before `LD B,(HL)` reads
+/// the register HL points to. The write to DIV happens in the third M-cycle of `LDH (DIV),A`.
+fn read_after_div_reset(setup: &[u8], after_reset: &[u8], nops: usize) -> u8 {
+    let mut program = vec![0xF3]; // DI
+    program.extend(setup);
+    program.extend([0xE0, 0x04]); // LDH (DIV),A
+    program.extend(after_reset);
+    program.extend(std::iter::repeat_n(0x00, nops));
+    program.extend([
+        0x46, // LD B,(HL)
+        0x18, 0xFE, // JR -2
+    ]);
+
+    let mut game_boy = GameBoy::new(&common::build_rom(&program)).unwrap();
+    game_boy.run_cycles(RUN_CYCLES);
+
+    game_boy.registers().b
+}
+
+/// DIV is bit 8-15 of the system counter, which counts T-cycles. `LD B,(HL)` reads in its second
+/// M-cycle, 63 and 64 M-cycles after the reset.
+#[test]
+fn div_increments_64_cycles_after_reset() {
+    let setup = [0x21, 0x04, 0xFF]; // LD HL,DIV
+
+    assert_eq!(read_after_div_reset(&setup, &[], 61), 0x00);
+    assert_eq!(read_after_div_reset(&setup, &[], 62), 0x01);
+}
+
+/// With TAC 0b101, TIMA increments every 4 M-cycles after the DIV reset. TIMA is set to 0xFE two
+/// M-cycles after the reset, so the increment after 8 M-cycles overflows. TIMA reads 0 for one
+/// M-cycle before it is reloaded from TMA.
+#[test]
+fn tima_is_reloaded_one_cycle_after_overflow() {
+    let setup = [
+        0x3E,
+        TIMA_MODULO,
+        0xE0,
+        0x06, // LD A,TIMA_MODULO ; LDH (TMA),A
+        0x3E,
+        0x05,
+        0xE0,
+        0x07, // LD A,0x05 ; LDH (TAC),A
+        0x21,
+        0x05,
+        0xFF, // LD HL,TIMA
+        0x3E,
+        0xFE, // LD A,0xFE
+    ];
+    let after_reset = [0x77]; // LD (HL),A
+
+    assert_eq!(read_after_div_reset(&setup, &after_reset, 3), 0xFF);
+    assert_eq!(read_after_div_reset(&setup, &after_reset, 4), 0x00);
+    assert_eq!(read_after_div_reset(&setup, &after_reset, 5), TIMA_MODULO);
+}